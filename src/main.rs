@@ -1,23 +1,36 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
-use std::process;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
 #[macro_use]
 extern crate lazy_static;
 
+use log::warn;
+
 mod swed;
 use swed::*;
 
 mod ynab;
 use ynab::*;
 
+#[cfg(test)]
+mod mock_server;
+
 struct ParsedPayeeMemo {
     date: Option<String>,
     memo: Option<String>,
     payee: String,
+    // False if the payee did not match any known payee formats
+    matched: bool,
 }
 
 /// Splits the string with given splitter, drops n first items
@@ -33,12 +46,20 @@ fn drop_words(s: &str, splitter: &str, n: usize) -> String {
 lazy_static! {
     // Vector of well-known vendor names that can show up before the asterisk in the payee field.
     static ref VENDORS: Vec<&'static str> = {
-        vec!["AIRBNB", "AUTOSTAVVIETA", "Patreon", "Kindle Svcs"]
+        vec!["AIRBNB", "AUTOSTAVVIETA", "Patreon"]
     };
 }
 
+/// Payee used for transactions that have no payee in the CSV
+const DEFAULT_PAYEE: &str = "Swedbank";
+
 impl ParsedPayeeMemo {
+    #[cfg(test)]
     pub fn from_str(payee: &str, m: &str) -> ParsedPayeeMemo {
+        ParsedPayeeMemo::with_default_payee(payee, m, DEFAULT_PAYEE)
+    }
+
+    pub fn with_default_payee(payee: &str, m: &str, default_payee: &str) -> ParsedPayeeMemo {
         let mut sanitized_memo = String::from(m).replace('\'', "").replace("  ", " ");
         let mut date = None;
 
@@ -48,45 +69,52 @@ impl ParsedPayeeMemo {
             } else {
                 drop_words(&sanitized_memo, " ", 6)
             };
-            date = m.split(' ').nth(2).map(String::from);
-        }
-
-        let (fmtd_payee, fmtd_memo) = match payee {
-            "MakeCommerce" => parse_makecommerce_memo(&sanitized_memo),
-            "Trustly Group AB" => parse_trustly_memo(&sanitized_memo),
-            "Paysera LT" => parse_paysera_memo(&sanitized_memo),
-            p if p.starts_with("AMZN") => (String::from("Amazon"), Some(String::from(&sanitized_memo))),
-            "" => (String::from("Swedbank"), Some(String::from(&sanitized_memo))),
-            _ => (
-                if let Some(vendor) = VENDORS.iter().find(|&&v| payee.starts_with(v)) {
-                    vendor.to_string()
-                } else {
-                    match payee {
-                        "SumUp" => String::from(sanitized_memo.trim_start_matches("SumUp *")),
-                        p if p.starts_with("Revolut**") => String::from("Revolut"),
-                        p if p.starts_with("PAYPAL *") => parse_paypal_payee(p),
-                        p if p.contains('*') => drop_words(payee, "*", 1).replace('\'', "").trim_start().to_string(),
-                        p => String::from(p).replace('\'', ""),
-                    }
-                },
-                match sanitized_memo {
-                    ref m if m.starts_with(payee) => None,
-                    ref m => Some(String::from(m)),
+            date = m.split(' ').nth(2).filter(|d| is_date(d)).map(String::from);
+        }
+
+        let mut matched = true;
+        let (fmtd_payee, fmtd_memo) = match MERCHANT_PARSERS.iter().find(|p| p.matches(payee)) {
+            Some(parser) => parser.parse(payee, &sanitized_memo),
+            None => match payee {
+                "" => match parse_card_fee_memo(&sanitized_memo) {
+                    Some(card_memo) => (String::from(CARD_FEE_PAYEE), Some(card_memo).filter(|m| !m.is_empty())),
+                    None => (String::from(default_payee), Some(String::from(&sanitized_memo))),
                 },
-            ),
+                _ => (
+                    if let Some(vendor) = VENDORS.iter().find(|&&v| payee.starts_with(v)) {
+                        vendor.to_string()
+                    } else {
+                        match payee {
+                            "SumUp" => String::from(sanitized_memo.trim_start_matches("SumUp *")),
+                            p if p.contains('*') => {
+                                matched = false;
+                                drop_words(payee, "*", 1).replace('\'', "").trim_start().to_string()
+                            }
+                            p => {
+                                matched = false;
+                                String::from(p).replace('\'', "")
+                            }
+                        }
+                    },
+                    memo_unless_payee(payee, &sanitized_memo),
+                ),
+            },
         };
 
         ParsedPayeeMemo {
             date,
             memo: fmtd_memo,
             payee: fmtd_payee,
+            matched,
         }
     }
 }
 
 // YNAB is using a "milliunit" for tx amounts: https://api.youneedabudget.com/#formats
+// Direction of the transaction is determined by the entry type, regardless of the amount sign.
 fn fmt_amount(amount: &str, tx_type: &EntryType) -> i64 {
     parse_i64_string(amount)
+        .map(i64::abs)
         .map(|v| match tx_type {
             EntryType::Debit => -10 * v,
             EntryType::Credit => 10 * v,
@@ -94,11 +122,47 @@ fn fmt_amount(amount: &str, tx_type: &EntryType) -> i64 {
         .unwrap_or(0)
 }
 
+/// Returns the amount of a CSV row in milliunits, either from the single amount column, or from
+/// the debit and credit amount columns, whichever is filled in. Returns None for rows that have
+/// neither an amount with a debit/credit indicator nor a debit or credit amount.
+fn row_amount(row: &SwedbankCsv) -> Option<i64> {
+    fn filled(a: Option<&str>) -> Option<&str> {
+        a.filter(|a| !a.trim().is_empty())
+    }
+    match (
+        &row.debit_or_credit,
+        filled(Some(&row.amount)),
+        filled(row.debit_amount.as_deref()),
+        filled(row.credit_amount.as_deref()),
+    ) {
+        (Some(entry_type), Some(amount), _, _) => Some(fmt_amount(amount, entry_type)),
+        (_, _, Some(debit), _) => Some(fmt_amount(debit, &EntryType::Debit)),
+        (_, _, _, Some(credit)) => Some(fmt_amount(credit, &EntryType::Credit)),
+        _ => None,
+    }
+}
+
+/// Amount of balance rows, in cents. Exports with separate debit and credit amount columns
+/// have positive balances in the credit column and negative ones in the debit column.
+fn row_balance(row: &SwedbankCsv) -> Option<i64> {
+    parse_i64_string(&row.amount)
+        .or_else(|| row.credit_amount.as_deref().and_then(parse_i64_string))
+        .or_else(|| row.debit_amount.as_deref().and_then(parse_i64_string).map(|b| -b.abs()))
+}
+
+// Memo suffixes of commissions that are charged for a specific transaction.
+const ROLLUP_SUFFIXES: &[&str] = &[" apkalpošanas komisija"];
+
 // Returns true if the given transaction contains extra processing fees that need
 // to be applied to the previous transaction.
 #[inline]
-fn needs_rollup(memo: &str, payment_type: &str) -> bool {
-    is_comission(payment_type) && memo.ends_with(" apkalpošanas komisija")
+fn needs_rollup(memo: &str, payment_type: &str, extra_suffixes: &[String]) -> bool {
+    is_comission(payment_type)
+        && ROLLUP_SUFFIXES
+            .iter()
+            .copied()
+            .chain(extra_suffixes.iter().map(String::as_str))
+            .any(|s| memo.ends_with(s))
 }
 #[inline]
 fn duplicate_transaction_id(payment_type: &str, payee: &str) -> bool {
@@ -115,232 +179,2100 @@ fn fmt_transaction_id(transaction_id: &str, payment_type: &str, payee: &str) ->
     }
 }
 
-/// Transforms date from DD.MM.YYYY to YYYY-MM-DD
+/// When a transaction has several commissions, they all get the same `_1` suffixed id.
+/// Numbers the following ones `_2`, `_3` and so on, so that each of them has a unique import id.
+fn number_duplicate_ids(txns: &mut [YnabTransaction]) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for t in txns.iter_mut() {
+        if let Some(transaction_id) = t.import_id.strip_suffix("_1") {
+            let count = counts.entry(String::from(transaction_id)).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                t.import_id = format!("{}_{}", transaction_id, count);
+            }
+        }
+    }
+}
+
+/// Returns true if the given string is a date in DD.MM.YYYY or DD.MM.YY format
+fn is_date(d: &str) -> bool {
+    let parts = d.split('.').collect::<Vec<&str>>();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+        && parts[0].len() <= 2
+        && parts[1].len() <= 2
+        && (parts[2].len() == 4 || parts[2].len() == 2)
+}
+
+/// Transforms date from DD.MM.YYYY to YYYY-MM-DD, zero-padding single digit days and months.
+/// Two digit years, used in foreign currency card purchase memos, are expanded to 20YY.
 fn fmt_date(d: &str) -> String {
-    let mut parts = d.split('.').collect::<Vec<&str>>();
-    parts.reverse();
+    let mut parts = d
+        .split('.')
+        .rev()
+        .map(|p| format!("{:0>2}", p))
+        .collect::<Vec<String>>();
+    if parts.len() == 3 && parts[0].len() == 2 {
+        parts[0] = format!("20{}", parts[0]);
+    }
     parts.join("-")
 }
 
-fn from_transaction_row(row: SwedbankCsv, account_id: &str) -> YnabTransaction {
-    let memo = ParsedPayeeMemo::from_str(&row.payee, &row.memo);
+/// Combines the CSV payee and memo into a single YNAB memo
+fn merge_payee_memo(payee: &str, memo: &str) -> Option<String> {
+    match (payee.trim(), memo.trim()) {
+        ("", "") => None,
+        (p, "") => Some(String::from(p)),
+        ("", m) => Some(String::from(m)),
+        (p, m) => Some(format!("{}: {}", p, m)),
+    }
+}
+
+/// Returns the amount and currency of foreign currency card purchases, e.g. "24.90 CHF"
+fn fx_details(memo: &str) -> Option<String> {
+    if !memo.starts_with("PIRKUMS ") || !is_foreign_currency_tx(memo) {
+        return None;
+    }
+    let words = memo.split(',').next()?.split_whitespace().collect::<Vec<&str>>();
+    match words.len() {
+        n if n > 2 => Some(words[n - 2..].join(" ")),
+        _ => None,
+    }
+}
+
+// Maximum length of imported memos, in characters
+const MAX_MEMO_LENGTH: usize = 200;
+
+/// Memo template that reproduces the default memo formatting
+const DEFAULT_MEMO_FORMAT: &str = "{memo} {source}";
+
+/// Values of the placeholders that can be used in memo templates
+#[derive(Default)]
+struct MemoFields<'a> {
+    /// Memo formatted the default way
+    memo: Option<&'a str>,
+    /// Archive code of the CSV row
+    reference: &'a str,
+    /// Payee as it is in the CSV
+    merchant: &'a str,
+    fx: Option<String>,
+    payment_type: &'a str,
+    source: Option<&'a str>,
+}
+
+impl MemoFields<'_> {
+    fn get(&self, placeholder: &str) -> &str {
+        match placeholder {
+            "memo" => self.memo.unwrap_or(""),
+            "reference" => self.reference,
+            "merchant" => self.merchant,
+            "fx" => self.fx.as_deref().unwrap_or(""),
+            "type" => self.payment_type,
+            "source" => self.source.unwrap_or(""),
+            _ => "",
+        }
+    }
+}
+
+// Placeholders that can be used in memo templates
+const MEMO_PLACEHOLDERS: &[&str] = &["memo", "reference", "merchant", "fx", "type", "source"];
+
+/// Checks that the braces in a memo template are balanced and all the placeholders are known
+fn validate_memo_format(format: &str) -> Result<(), String> {
+    let is_brace = |c: char| c == '{' || c == '}';
+    let mut rest = format;
+    while let Some(i) = rest.find(is_brace) {
+        let after = &rest[i + 1..];
+        let end = match after.find(is_brace) {
+            Some(end) if rest[i..].starts_with('{') && after[end..].starts_with('}') => end,
+            _ => return Err(format!("unbalanced braces in {:?}", format)),
+        };
+        let placeholder = after[..end].trim();
+        if !MEMO_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {{{}}}, expected one of: {}",
+                placeholder,
+                MEMO_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &after[end + 1..];
+    }
+    Ok(())
+}
+
+/// Splits a memo template into the text before the first placeholder
+/// and (placeholder, text after it) pairs
+fn parse_memo_format(format: &str) -> (&str, Vec<(&str, &str)>) {
+    let mut parts = Vec::new();
+    let (prefix, mut rest) = match format.find('{') {
+        Some(i) => (&format[..i], &format[i..]),
+        None => (format, ""),
+    };
+    while let Some((placeholder, after)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+        let end = after.find('{').unwrap_or(after.len());
+        parts.push((placeholder.trim(), &after[..end]));
+        rest = &after[end..];
+    }
+    (prefix, parts)
+}
+
+/// Fills in the memo template. Empty placeholders are left out together with the text separating
+/// them from the other placeholders, text before the first and after the last placeholder is kept.
+/// Returns None if all the placeholders are empty.
+fn render_memo(prefix: &str, parts: &[(&str, &str)], fields: &MemoFields) -> Option<String> {
+    let suffix = parts.last().map_or("", |(_, after)| *after);
+    let mut values = String::new();
+    let mut separator = "";
+    for (placeholder, after) in parts {
+        let value = fields.get(placeholder).trim();
+        if !value.is_empty() {
+            if !values.is_empty() {
+                values.push_str(separator);
+            }
+            values.push_str(value);
+            separator = after;
+        }
+    }
+    if values.is_empty() && !parts.is_empty() {
+        return None;
+    }
+    Some(String::from(format!("{}{}{}", prefix, values, suffix).trim()))
+}
+
+/// Builds the imported memo from the given template, shortening the {memo} part if needed
+/// so that the whole memo fits within MAX_MEMO_LENGTH characters
+fn build_memo(format: &str, fields: &MemoFields) -> Option<String> {
+    let (prefix, parts) = parse_memo_format(format);
+    let mut memo = render_memo(prefix, &parts, fields)?;
+    let excess = memo.chars().count().saturating_sub(MAX_MEMO_LENGTH);
+    if excess > 0 {
+        if let Some(m) = fields.memo {
+            let length = m.chars().count().saturating_sub(excess);
+            let shortened = m.chars().take(length).collect::<String>();
+            let fields = MemoFields {
+                memo: Some(shortened.trim_end()),
+                fx: fields.fx.clone(),
+                ..*fields
+            };
+            memo = render_memo(prefix, &parts, &fields)?;
+        }
+    }
+    Some(memo.chars().take(MAX_MEMO_LENGTH).collect::<String>()).filter(|m| !m.is_empty())
+}
+
+fn from_transaction_row(row: SwedbankCsv, account_id: &str, options: &ImportOptions) -> YnabTransaction {
+    let memo = ParsedPayeeMemo::with_default_payee(&row.payee, &row.memo, &options.default_payee);
+    let (payee_name, fmtd_memo, payee_matched) = if options.merge_payment_types.contains(&row.payment_type) {
+        (
+            options.merged_payee.clone(),
+            merge_payee_memo(&row.payee, &row.memo),
+            true,
+        )
+    } else {
+        (memo.payee, memo.memo, memo.matched)
+    };
+    // Rows without an amount are rejected when the CSV is read
+    let amount = row_amount(&row).unwrap_or_default();
+    let fields = MemoFields {
+        memo: fmtd_memo.as_deref(),
+        reference: &row.transaction_id,
+        merchant: &row.payee,
+        fx: fx_details(&row.memo),
+        payment_type: &row.payment_type,
+        source: options.source_tag.as_deref(),
+    };
+    let memo_text = build_memo(&options.memo_format, &fields);
     YnabTransaction {
         import_id: fmt_transaction_id(&row.transaction_id, &row.payment_type, &row.payee),
         date: fmt_date(&memo.date.unwrap_or(row.date)),
-        payee_name: memo.payee,
-        memo: memo.memo,
-        cleared: String::from("cleared"),
-        amount: fmt_amount(&row.amount, &row.debit_or_credit),
+        payee_name,
+        memo: memo_text,
+        cleared: String::from(options.cleared_state(&row.payment_type)),
+        amount,
         account_id: String::from(account_id),
-        needs_rollup: needs_rollup(&row.memo, &row.payment_type),
+        category_id: None,
+        flag_color: None,
+        payee_id: None,
+        needs_rollup: needs_rollup(&row.memo, &row.payment_type, &options.rollup_suffixes),
+        raw_payee: row.payee,
+        payee_matched,
+    }
+}
+
+/// Returns raw CSV payees that did not match any known payee formats, most frequent first
+fn unmatched_payees(txns: &[YnabTransaction]) -> Vec<(&str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for t in txns.iter().filter(|t| !t.payee_matched) {
+        *counts.entry(&t.raw_payee).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<(&str, usize)>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+/// Prints CSV payees that did not match any known payee formats, for each account
+fn print_unmatched_payees(imports: &[AccountImport]) {
+    for import in imports {
+        if imports.len() > 1 {
+            println!("== {}", import.currency);
+        }
+        println!("Payees that did not match any known formats:");
+        for (payee, count) in unmatched_payees(&import.txns) {
+            println!("  {} {}", count, payee);
+        }
+    }
+}
+
+/// Returns raw CSV payees with the payees they are imported as, whether they matched any known
+/// payee formats and the number of their transactions, most frequent first
+fn payee_preview(txns: &[YnabTransaction]) -> Vec<(&str, &str, bool, usize)> {
+    let mut counts: HashMap<(&str, &str, bool), usize> = HashMap::new();
+    for t in txns.iter() {
+        *counts
+            .entry((&t.raw_payee, &t.payee_name, t.payee_matched))
+            .or_insert(0) += 1;
     }
+    let mut counts = counts
+        .into_iter()
+        .map(|((raw, payee, matched), count)| (raw, payee, matched, count))
+        .collect::<Vec<(&str, &str, bool, usize)>>();
+    counts.sort_by(|a, b| b.3.cmp(&a.3).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+    counts
 }
 
-fn run(csv_file: File, client: YnabClient) -> Result<(), Box<dyn Error>> {
+/// Prints how payees in the CSVs are imported, in all currencies. Nothing is sent to YNAB.
+fn preview_payees(csv_paths: &[&str], options: &ImportOptions) -> Result<(), Box<dyn Error>> {
     let mut txns: Vec<YnabTransaction> = Vec::new();
-    let mut csv_balance: i64 = 0;
-
-    let budget_currency = client.get_budget_currency()?;
-
-    let mut rdr = csv::ReaderBuilder::new().delimiter(b';').from_reader(csv_file);
-    for row in rdr.deserialize() {
-        let record: SwedbankCsv = row?;
-        if record.currency == budget_currency {
-            match record.record_type {
-                RecordType::Transaction => txns.push(from_transaction_row(record, &client.account_id)),
-                RecordType::EndBalance => {
-                    if let Some(b) = parse_i64_string(&record.amount) {
-                        csv_balance = b
-                    }
-                }
-                _ => {}
+    for path in csv_paths {
+        let csv = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        for record in read_rows(csv, options.skip_lines)? {
+            if matches!(record.record_type, RecordType::Transaction) {
+                txns.push(from_transaction_row(record, "", options));
             }
         }
     }
 
+    for (raw_payee, payee, matched, count) in payee_preview(&txns) {
+        let unmatched = if matched { "" } else { " (unmatched)" };
+        println!("{:>5} {:?} -> {:?}{}", count, raw_payee, payee, unmatched);
+    }
+    Ok(())
+}
+
+/// Payee for imported interest rows
+const INTEREST_PAYEE: &str = "Swedbank interest";
+
+/// Interest can be both earned (credit) and charged (debit), so the amount is signed the same
+/// way as for regular transactions.
+fn from_interest_row(row: SwedbankCsv, account_id: &str, options: &ImportOptions) -> YnabTransaction {
+    // Rows without an amount are rejected when the CSV is read
+    let amount = row_amount(&row).unwrap_or_default();
+    let date = fmt_date(&row.date);
+    let fields = MemoFields {
+        memo: Some(row.memo.as_str()).filter(|m| !m.is_empty()),
+        reference: &row.transaction_id,
+        merchant: &row.payee,
+        payment_type: &row.payment_type,
+        source: options.source_tag.as_deref(),
+        ..MemoFields::default()
+    };
+    let memo = build_memo(&options.memo_format, &fields);
+    YnabTransaction {
+        import_id: match row.transaction_id.as_str() {
+            "" => format!("INT:{}:{}", date, amount),
+            id => String::from(id),
+        },
+        date,
+        payee_name: String::from(INTEREST_PAYEE),
+        memo,
+        cleared: String::from(options.cleared_state(&row.payment_type)),
+        amount,
+        account_id: String::from(account_id),
+        category_id: None,
+        flag_color: None,
+        payee_id: None,
+        needs_rollup: false,
+        raw_payee: row.payee,
+        payee_matched: true,
+    }
+}
+
+/// Applies commission amounts to the transactions they were charged for,
+/// returns the number and total amount of the rolled up commissions
+fn rollup_commissions(txns: &mut Vec<YnabTransaction>) -> (usize, i64) {
+    let (mut count, mut total) = (0, 0);
     let mut i = 0;
     while i != txns.len() {
-        if txns[i].needs_rollup {
+        if i == 0 && txns[i].needs_rollup {
+            // The transaction of a commission at the start of a statement is in the previous statement
+            warn!(
+                "Commission {} has no transaction to be added to, importing it separately",
+                txns[i].import_id
+            );
+            txns[i].needs_rollup = false;
+            i += 1;
+        } else if txns[i].needs_rollup {
             let to_apply = txns[i].amount;
             let txn = txns.remove(i - 1);
             txns.insert(i - 1, txn.add_amount(to_apply));
             txns.remove(i);
+            count += 1;
+            total += to_apply;
         } else {
             i += 1;
         }
     }
+    (count, total)
+}
 
-    let mut imported: usize = 0;
-    let mut duplicates: usize = 0;
-
-    for t in txns.rchunks(50) {
-        let res = client.post_transactions(t)?;
-        imported += res.transactions.len();
-        duplicates += res.duplicate_import_ids.len();
+/// Keeps commissions as separate transactions in the given category, instead of rolling them up
+fn categorize_fees(txns: &mut [YnabTransaction], category_id: &str) {
+    for t in txns.iter_mut().filter(|t| t.needs_rollup) {
+        t.category_id = Some(String::from(category_id));
+        t.needs_rollup = false;
     }
+}
 
-    println!("{} new transactions imported", imported);
-    println!("{} duplicates found", duplicates);
+// Flag color of transactions that look like duplicates of other transactions in the CSV
+const POSSIBLE_DUPLICATE_FLAG: &str = "yellow";
 
-    if imported > 0 {
-        println!("See new transactions in app: {}", client.app_account_uri());
+/// Flags transactions with the same date, payee and amount, returns the number of flagged transactions.
+/// Such transactions have different import ids, so they are all imported, but might need a review.
+fn flag_possible_duplicates(txns: &mut [YnabTransaction]) -> usize {
+    let mut counts: HashMap<(String, String, i64), usize> = HashMap::new();
+    for t in txns.iter() {
+        *counts
+            .entry((t.date.clone(), t.payee_name.clone(), t.amount))
+            .or_insert(0) += 1;
     }
 
-    let ynab_balance = client.get_acccount_balance()? / 10;
-    if ynab_balance != csv_balance {
-        println!("== Warning: balance mismatch:");
-        println!("Final CSV balance: {}", csv_balance as f32 / 100.0);
-        println!("Current YNAB balance: {}", ynab_balance as f32 / 100.0);
-        println!("Difference: {}", (ynab_balance - csv_balance) as f32 / 100.0);
+    let mut flagged = 0;
+    for t in txns.iter_mut() {
+        if counts[&(t.date.clone(), t.payee_name.clone(), t.amount)] > 1 {
+            t.flag_color = Some(String::from(POSSIBLE_DUPLICATE_FLAG));
+            flagged += 1;
+        }
     }
+    flagged
+}
 
-    Ok(())
+/// Parses an amount in major currency units, e.g. "0.50", into milliunits
+fn parse_major_units(amount: &str) -> Option<i64> {
+    amount
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|a| a.is_finite())
+        .map(|a| (a * 1000.0).round() as i64)
 }
 
-fn main() -> std::io::Result<()> {
-    env_logger::init();
-    let args = App::new(env!("CARGO_PKG_NAME"))
-        .version(env!("CARGO_PKG_VERSION"))
-        .arg(
-            Arg::with_name("CSV_PATH")
-                .help("Path for Swedbank CSV export")
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("token")
-                .short("t")
-                .required(true)
-                .env("YNAB_TOKEN")
-                .value_name("TOKEN")
-                .help("YNAB personal acces token"),
-        )
-        .arg(
-            Arg::with_name("budget")
-                .short("b")
-                .required(true)
-                .env("YNAB_BUDGET")
-                .value_name("BUDGET")
-                .help("YNAB budget id"),
-        )
-        .arg(
-            Arg::with_name("account")
-                .short("a")
-                .required(true)
-                .env("YNAB_ACCOUNT")
-                .value_name("ACCOUNT")
-                .help("YNAB account id"),
-        )
-        .get_matches();
+/// Removes transactions that are smaller than the given milliunit amount, returns the number
+/// of removed transactions. Commissions should be rolled up before, so that they count towards
+/// the amount of their transactions.
+fn skip_small_transactions(txns: &mut Vec<YnabTransaction>, min_amount: i64) -> usize {
+    let count = txns.len();
+    txns.retain(|t| t.amount.abs() >= min_amount);
+    count - txns.len()
+}
 
-    let client = YnabClient::new(
-        args.value_of("budget").unwrap_or("").to_string(),
-        args.value_of("account").unwrap_or("").to_string(),
-        args.value_of("token").unwrap_or(""),
-    );
+/// Rolls up commissions into their transactions, unless they are kept as separate transactions.
+/// Returns the number and total amount of the rolled up commissions.
+fn apply_commissions(txns: &mut Vec<YnabTransaction>, options: &ImportOptions) -> (usize, i64) {
+    match &options.fee_category {
+        Some(category_id) => {
+            categorize_fees(txns, category_id);
+            (0, 0)
+        }
+        None if options.no_rollup => (0, 0),
+        None => rollup_commissions(txns),
+    }
+}
 
-    if let Err(err) = run(File::open(args.value_of("CSV_PATH").unwrap())?, client) {
-        println!("{}", err);
-        process::exit(1);
+/// Returns the number of days since 1970-01-01 for a YYYY-MM-DD date
+fn days_since_epoch(date: &str) -> Option<i64> {
+    let mut parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(Some(y)), Some(Some(m)), Some(Some(d))) => (y, m, d),
+        _ => return None,
+    };
+
+    // Days from civil algorithm, with years starting on March 1st
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+fn days_since_epoch_today() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64 / 86400)
+}
+
+/// Returns the age in days of the newest transaction, given today's date as days since epoch
+fn statement_age(txns: &[YnabTransaction], today: i64) -> Option<i64> {
+    txns.iter()
+        .filter_map(|t| days_since_epoch(&t.date))
+        .max()
+        .map(|newest| today - newest)
+}
+
+/// Stores the server knowledge of the given budget in a JSON file, keeping the values of other budgets
+fn save_server_knowledge(path: &str, budget_id: &str, server_knowledge: i64) -> Result<(), Box<dyn Error>> {
+    let mut knowledge: HashMap<String, i64> = match std::fs::read_to_string(path) {
+        Ok(s) => serde_json::from_str(&s)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+    knowledge.insert(String::from(budget_id), server_knowledge);
+    std::fs::write(path, serde_json::to_string_pretty(&knowledge)?)?;
+    Ok(())
+}
+
+// Import ids of already imported transactions, by account id
+type Ledger = HashMap<String, BTreeSet<String>>;
+
+/// Reads the ledger of already imported transactions, a missing file is an empty ledger
+fn read_ledger(path: &str) -> Result<Ledger, Box<dyn Error>> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => Ok(serde_json::from_str(&s)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
     }
+}
+
+/// Adds import ids of the given account to the ledger file, keeping the ids of other accounts
+fn save_ledger(path: &str, account_id: &str, txns: &[YnabTransaction]) -> Result<(), Box<dyn Error>> {
+    let mut ledger = read_ledger(path)?;
+    ledger
+        .entry(String::from(account_id))
+        .or_default()
+        .extend(txns.iter().map(|t| t.import_id.clone()));
+    std::fs::write(path, serde_json::to_string_pretty(&ledger)?)?;
     Ok(())
 }
 
-#[cfg(test)]
+/// Removes transactions that are already in the ledger and returns them
+fn skip_imported_transactions(txns: &mut Vec<YnabTransaction>, imported: &BTreeSet<String>) -> Vec<YnabTransaction> {
+    let (known, new) = txns.drain(..).partition(|t| imported.contains(&t.import_id));
+    *txns = new;
+    known
+}
 
-mod tests {
-    use super::*;
+/// Returns the total debit and credit amounts of the given transactions in cents
+fn turnover(txns: &[YnabTransaction]) -> (i64, i64) {
+    txns.iter().fold((0, 0), |(debit, credit), t| {
+        if t.amount < 0 {
+            (debit - t.amount / 10, credit)
+        } else {
+            (debit, credit + t.amount / 10)
+        }
+    })
+}
 
-    #[test]
-    fn test_absent_payee() {
-        assert_eq!(ParsedPayeeMemo::from_str("", "Payment").payee, "Swedbank");
+fn check_turnover(label: &str, csv_turnover: Option<i64>, imported: i64) {
+    match csv_turnover {
+        Some(t) if t != imported => {
+            println!("== Warning: {} turnover mismatch:", label);
+            println!("CSV turnover: {}", t as f32 / 100.0);
+            println!("Imported transactions: {}", imported as f32 / 100.0);
+            println!("Difference: {}", (imported - t) as f32 / 100.0);
+        }
+        Some(_) => {}
+        None => println!("== Warning: {} turnover not found in CSV", label),
     }
+}
 
-    #[test]
-    fn test_basic_cc_payment() {
-        let r = ParsedPayeeMemo::from_str("Abc", "PIRKUMS 0***1 28.12.2021 5.00 EUR (123456) Abc");
-        assert_eq!(None, r.memo);
-        assert_eq!(String::from("Abc"), r.payee);
-    }
+/// Returns true if importing the given number of transactions has to be confirmed
+fn needs_confirmation(count: usize, confirm_threshold: Option<usize>) -> bool {
+    confirm_threshold.is_some_and(|t| count > t)
+}
 
-    #[test]
-    fn test_foreign_currency_cc_payment() {
-        let r = ParsedPayeeMemo::from_str(
-            "Abc",
-            "PIRKUMS 0******1 30.07.24 13:07 24.90 CHF, ATTIECĪBĀ PRET ECB VALŪTAS KURSU 2.3% (123456) Abc",
-        );
-        assert_eq!(None, r.memo);
-        assert_eq!(String::from("Abc"), r.payee);
-    }
+/// Asks the user to confirm the import, returns true if the import should continue
+fn confirm_import(count: usize, account_uri: &str) -> io::Result<bool> {
+    print!(
+        "About to import {} transactions into {}, continue? [y/N] ",
+        count, account_uri
+    );
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
 
-    #[test]
-    fn test_sumup_payee() {
-        assert_eq!(ParsedPayeeMemo::from_str("SumUp", "SumUp  *Foobar 1").payee, "Foobar 1");
-    }
+/// Totals of the posted transaction chunks
+struct PostResult {
+    imported: usize,
+    duplicates: usize,
+    server_knowledge: Option<i64>,
+    /// YNAB ids of the imported transactions
+    transaction_ids: Vec<String>,
+}
 
-    #[test]
-    fn test_sumup_payee2() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("SumUp", "PIRKUMS 0***1 28.12.2021 5.00 EUR (123456) SumUp  *Abc").payee,
-            "Abc"
-        );
+/// Deletes transactions that were imported before the import failed
+fn rollback_import(client: &YnabClient, transaction_ids: &[String]) {
+    println!(
+        "Import failed, deleting {} already imported transactions",
+        transaction_ids.len()
+    );
+    let failed = transaction_ids
+        .iter()
+        .filter(|id| client.delete_transaction(id).is_err())
+        .map(String::as_str)
+        .collect::<Vec<&str>>();
+    if !failed.is_empty() {
+        println!("Failed to delete transactions: {}", failed.join(", "));
     }
+}
 
-    #[test]
-    fn test_izettle_payee() {
-        assert_eq!(ParsedPayeeMemo::from_str("IZ *Payee222", "memo!").payee, "Payee222");
-    }
+/// Posts transactions to YNAB in chunks, with at most `concurrency` chunks posted at the same time.
+/// No new chunks are posted after a chunk fails. In atomic mode, transactions from the already
+/// posted chunks are deleted if posting any of the chunks fails.
+fn post_chunks(
+    client: &YnabClient,
+    txns: &[YnabTransaction],
+    chunk_order: ChunkOrder,
+    atomic: bool,
+    concurrency: usize,
+) -> Result<PostResult, Box<dyn Error>> {
+    let chunks = ordered_chunks(txns, 50, chunk_order).collect::<Vec<&[YnabTransaction]>>();
+    let next_chunk = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    // Errors are converted to strings, as they are passed between threads
+    let responses: Mutex<Vec<(usize, Result<PostTransactionsResponseData, String>)>> = Mutex::new(Vec::new());
 
-    #[test]
-    fn test_gumroad_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("GUM.CO/CC* Gumroad1", "memo!").payee,
-            "Gumroad1"
-        );
-    }
+    thread::scope(|s| {
+        for _ in 0..concurrency.max(1) {
+            s.spawn(|| loop {
+                let i = next_chunk.fetch_add(1, Ordering::SeqCst);
+                if i >= chunks.len() || failed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let res = client.post_transactions(chunks[i]).map_err(|e| e.to_string());
+                if res.is_err() {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                responses.lock().unwrap().push((i, res));
+            });
+        }
+    });
 
-    #[test]
-    fn test_amazon_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("AMZN Digital*Foo 111", "memo!").payee,
-            "Amazon"
-        );
-    }
+    let mut responses = responses.into_inner().unwrap();
+    responses.sort_by_key(|(i, _)| *i);
 
-    #[test]
-    fn test_kindle_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("Kindle Svcs*0F00T0000 00000 000-000-0000", "memo!").payee,
-            "Kindle Svcs"
-        );
-    }
+    let mut result = PostResult {
+        imported: 0,
+        duplicates: 0,
+        server_knowledge: None,
+        transaction_ids: Vec::new(),
+    };
+    let mut error = None;
 
-    #[test]
-    fn test_patreon_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("Patreon* Membership", "memo!").payee,
-            "Patreon"
-        );
+    for (_, res) in responses {
+        match res {
+            Ok(res) => {
+                result.imported += res.transactions.len();
+                result.duplicates += res.duplicate_import_ids.len();
+                result.server_knowledge = result.server_knowledge.max(Some(res.server_knowledge));
+                result.transaction_ids.extend(res.transaction_ids);
+            }
+            Err(err) => {
+                error = error.or(Some(err));
+            }
+        }
     }
 
-    #[test]
-    fn test_airbnb_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str("AIRBNB * FOOBAR 000 999-101-1111", "memo!").payee,
-            "AIRBNB"
-        );
+    if let Some(err) = error {
+        if atomic {
+            rollback_import(client, &result.transaction_ids);
+        }
+        return Err(err.into());
     }
 
-    #[test]
-    fn test_escapable_payee() {
-        assert_eq!(ParsedPayeeMemo::from_str("'Foobar", "Test").payee, "Foobar");
-    }
+    Ok(result)
+}
 
-    #[test]
-    fn test_revolut_payee() {
-        assert_eq!(
-            ParsedPayeeMemo::from_str(
-                "Revolut**1234* D02 R296 Dublin",
-                "PIRKUMS 123******1234 01.08.2023 10.00 EUR (123) Revolut**1234* D02 R296 Dublin"
+/// Writes transactions to a JSON file, so that they can be imported later with `import-json`
+fn export_transactions<'a>(
+    path: &str,
+    txns: impl IntoIterator<Item = &'a YnabTransaction>,
+) -> Result<(), Box<dyn Error>> {
+    let txns = txns.into_iter().collect::<Vec<&YnabTransaction>>();
+    std::fs::write(path, serde_json::to_string_pretty(&txns)?)?;
+    Ok(())
+}
+
+/// Reads transactions from a JSON file written by `export_transactions`
+fn read_exported_transactions(path: &str) -> Result<Vec<YnabTransaction>, Box<dyn Error>> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Posts previously exported transactions to YNAB as they are, without any CSV processing
+fn import_json(path: &str, client: YnabClient, options: &ImportOptions) -> Result<(), Box<dyn Error>> {
+    let txns = read_exported_transactions(path)?;
+    let PostResult {
+        imported, duplicates, ..
+    } = post_chunks(&client, &txns, options.chunk_order, options.atomic, options.concurrency)?;
+
+    println!("{} new transactions imported", imported);
+    println!("{} duplicates found", duplicates);
+
+    if imported > 0 {
+        println!("See new transactions in app: {}", client.app_account_uri());
+    }
+    Ok(())
+}
+
+/// Order in which transaction chunks are posted to YNAB
+#[derive(Clone, Copy)]
+enum ChunkOrder {
+    /// First CSV rows are posted first
+    Forward,
+    /// Last CSV rows are posted first
+    Reverse,
+}
+
+/// Splits transactions into chunks of the given size, in the order they should be posted.
+///
+/// Reverse order is the default for historical reasons, it does not matter for commission
+/// rollup, as that is done on the whole statement before any chunks are posted. With more than one
+/// chunk posted at a time, the chunks are started in this order but can finish in any order.
+fn ordered_chunks(
+    txns: &[YnabTransaction],
+    size: usize,
+    order: ChunkOrder,
+) -> Box<dyn Iterator<Item = &[YnabTransaction]> + '_> {
+    match order {
+        ChunkOrder::Forward => Box::new(txns.chunks(size)),
+        ChunkOrder::Reverse => Box::new(txns.rchunks(size)),
+    }
+}
+
+/// Normalizes ISO currency codes for comparison, e.g. " eur" -> "EUR"
+fn normalize_currency(currency: &str) -> String {
+    currency.trim().to_uppercase()
+}
+
+/// Returns a transaction that brings the YNAB account balance in line with the CSV,
+/// given the balance difference in cents.
+fn adjustment_transaction(account_id: &str, date: &str, difference: i64, cleared: &str) -> YnabTransaction {
+    let amount = difference * 10;
+    YnabTransaction {
+        import_id: format!("ADJ:{}:{}", date, amount),
+        date: String::from(date),
+        payee_name: String::from("Balance Adjustment"),
+        memo: Some(String::from(
+            "Balance adjustment to reconcile with the Swedbank statement",
+        )),
+        cleared: String::from(cleared),
+        amount,
+        account_id: String::from(account_id),
+        category_id: None,
+        flag_color: None,
+        payee_id: None,
+        needs_rollup: false,
+        raw_payee: String::new(),
+        payee_matched: true,
+    }
+}
+
+/// Balance that the YNAB account balance is compared against after the import
+enum BalanceCheck {
+    /// Final balance of the CSV statement
+    EndBalance,
+    /// Opening balance of the CSV statement with all the imported transactions applied
+    StartBalance,
+}
+
+/// Returns the expected account balance in cents, given the statement
+/// opening balance in cents and the imported transactions.
+fn balance_from_start(start_balance: i64, txns: &[YnabTransaction]) -> i64 {
+    start_balance + txns.iter().map(|t| t.amount).sum::<i64>() / 10
+}
+
+/// Splits transactions into ones that are not yet in YNAB and ones that are,
+/// based on the import ids of the existing YNAB transactions.
+fn diff_transactions<'a>(
+    txns: &'a [YnabTransaction],
+    existing_import_ids: &HashSet<String>,
+) -> (Vec<&'a YnabTransaction>, Vec<&'a YnabTransaction>) {
+    txns.iter().partition(|t| !existing_import_ids.contains(&t.import_id))
+}
+
+fn print_transaction(t: &YnabTransaction) {
+    println!(
+        "  {} {} {} ({})",
+        t.date,
+        t.payee_name,
+        t.amount as f32 / 1000.0,
+        t.import_id
+    );
+}
+
+/// Prints which transactions would be created and which would be skipped, without importing anything
+fn print_diff(txns: &[YnabTransaction], client: &YnabClient) -> Result<(), Box<dyn Error>> {
+    let existing_import_ids = match txns.iter().map(|t| &t.date).min() {
+        Some(since_date) => client
+            .get_transactions(since_date)?
+            .into_iter()
+            .filter_map(|t| t.import_id)
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let (new, existing) = diff_transactions(txns, &existing_import_ids);
+
+    println!("{} new transactions would be imported", new.len());
+    new.into_iter().for_each(print_transaction);
+    println!("{} transactions already exist in YNAB", existing.len());
+    existing.into_iter().for_each(print_transaction);
+
+    Ok(())
+}
+
+/// Checks that the access token, budget and account work, printing the budget currency and
+/// account balance. Nothing is changed in YNAB.
+fn check(client: &YnabClient) -> Result<(), Box<dyn Error>> {
+    match client.get_user_id() {
+        Ok(_) => println!("Access token: OK"),
+        Err(e) if e.status() == Some(reqwest::StatusCode::UNAUTHORIZED) => {
+            return Err(format!("Access token: FAILED, the token is invalid or has expired\n{}", e).into())
+        }
+        Err(e) if e.status().is_some() || e.is_decode() => return Err(format!("YNAB API: FAILED\n{}", e).into()),
+        Err(e) => return Err(format!("Connection to YNAB: FAILED\n{}", e).into()),
+    }
+
+    let currency = client
+        .get_budget_currency()
+        .map_err(|e| format!("Budget {}: FAILED\n{}", client.budget_id, e))?;
+    println!("Budget {}: OK, currency {}", client.budget_id, currency);
+
+    let balance = client
+        .get_acccount_balance()
+        .map_err(|e| format!("Account {}: FAILED\n{}", client.account_id, e))?;
+    println!(
+        "Account {}: OK, balance {:.2} {}",
+        client.account_id,
+        balance as f64 / 1000.0,
+        currency
+    );
+    Ok(())
+}
+
+/// Options that control how statements are imported.
+/// Defaults match the defaults of the command line options.
+struct ImportOptions {
+    /// Balance that the YNAB account balance is compared against after the import
+    balance_check: BalanceCheck,
+    /// Cleared state of imported transactions
+    cleared: String,
+    /// Cleared states of transactions of specific payment types, overriding `cleared`
+    cleared_map: HashMap<String, String>,
+    /// Payee for transactions that have no payee in the CSV
+    default_payee: String,
+    /// List new and already imported transactions instead of importing them
+    diff: bool,
+    /// Category of commissions that are imported as separate transactions instead of being rolled up
+    fee_category: Option<String>,
+    /// Import commissions as separate transactions
+    no_rollup: bool,
+    chunk_order: ChunkOrder,
+    /// Maximum age of the newest transaction in the CSV, in days
+    max_age: Option<i64>,
+    /// Refuse to import statements older than `max_age`
+    strict: bool,
+    knowledge_file: Option<String>,
+    turnover_check: bool,
+    /// Additional memo suffixes of commissions
+    rollup_suffixes: Vec<String>,
+    /// Number of transactions above which the import has to be confirmed, None to never ask
+    confirm_threshold: Option<usize>,
+    /// Payment types of rows whose payee and memo are combined into the memo
+    merge_payment_types: Vec<String>,
+    /// Payee of rows with combined payee and memo
+    merged_payee: String,
+    atomic: bool,
+    source_tag: Option<String>,
+    /// Template of imported memos, see `build_memo`
+    memo_format: String,
+    import_interest: bool,
+    print_unmatched: bool,
+    create_adjustment: bool,
+    export_path: Option<String>,
+    /// Minimum amount of imported transactions, in milliunits
+    min_amount: Option<i64>,
+    /// Currency and YNAB account id pairs for importing each currency into its own account
+    currency_map: Vec<(String, String)>,
+    /// Flag transactions with the same date, payee and amount
+    flag_possible_duplicates: bool,
+    /// Print every CSV row to stderr as it is read
+    dump_raw: bool,
+    /// Number of lines before the CSV header row, detected automatically if not given
+    skip_lines: Option<usize>,
+    /// JSON file with import ids of already imported transactions, which are not posted again
+    ledger_file: Option<String>,
+    /// Maximum number of transaction chunks that are posted at the same time
+    concurrency: usize,
+    /// YNAB payee ids of payees that are posted by id instead of by name
+    payee_ids: HashMap<String, String>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            balance_check: BalanceCheck::EndBalance,
+            cleared: String::from("cleared"),
+            cleared_map: HashMap::new(),
+            default_payee: String::from(DEFAULT_PAYEE),
+            diff: false,
+            fee_category: None,
+            no_rollup: false,
+            chunk_order: ChunkOrder::Reverse,
+            max_age: None,
+            strict: false,
+            knowledge_file: None,
+            turnover_check: false,
+            rollup_suffixes: vec![],
+            confirm_threshold: Some(100),
+            merge_payment_types: vec![],
+            merged_payee: String::from("Transfer"),
+            atomic: false,
+            source_tag: None,
+            memo_format: String::from(DEFAULT_MEMO_FORMAT),
+            import_interest: false,
+            print_unmatched: false,
+            create_adjustment: false,
+            export_path: None,
+            min_amount: None,
+            currency_map: vec![],
+            flag_possible_duplicates: false,
+            dump_raw: false,
+            skip_lines: None,
+            ledger_file: None,
+            concurrency: 4,
+            payee_ids: HashMap::new(),
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Returns the cleared state of transactions of the given payment type
+    fn cleared_state(&self, payment_type: &str) -> &str {
+        self.cleared_map.get(payment_type).unwrap_or(&self.cleared)
+    }
+}
+
+// Cleared states supported by YNAB
+const CLEARED_STATES: &[&str] = &["cleared", "uncleared", "reconciled"];
+
+/// Parses a PAYMENT_TYPE=STATE cleared state override
+fn parse_cleared_mapping(mapping: &str) -> Option<(String, String)> {
+    mapping
+        .split_once('=')
+        .map(|(payment_type, state)| (payment_type.trim(), state.trim()))
+        .filter(|(payment_type, state)| !payment_type.is_empty() && CLEARED_STATES.contains(state))
+        .map(|(payment_type, state)| (String::from(payment_type), String::from(state)))
+}
+
+/// Transactions and balances read from a single CSV statement
+#[derive(Default)]
+struct Statement {
+    txns: Vec<YnabTransaction>,
+    start_balance: i64,
+    end_balance: i64,
+    end_date: Option<String>,
+    debit_turnover: Option<i64>,
+    credit_turnover: Option<i64>,
+    // Number and total amount of commissions rolled up into their transactions
+    rolled_up_fees: (usize, i64),
+}
+
+// Names of the first CSV column, used for finding the header row
+const HEADER_KEYWORDS: &[&str] = &["Ieraksta tips", "Reatüüp"];
+
+/// Returns the CSV contents starting from the header row. Unless the number of lines to skip is
+/// given, any lines before the first line that contains a known column name are skipped.
+/// Fails if the given number of lines does not leave a header row as the first line.
+fn strip_preamble(contents: &str, skip_lines: Option<usize>) -> Result<&str, Box<dyn Error>> {
+    let is_header = |l: &str| HEADER_KEYWORDS.iter().any(|k| l.contains(k));
+    let given_skip_lines = skip_lines.is_some();
+    let skip_lines = skip_lines.unwrap_or_else(|| contents.lines().position(is_header).unwrap_or(0));
+    let offset = contents
+        .split_inclusive('\n')
+        .take(skip_lines)
+        .map(str::len)
+        .sum::<usize>();
+    match &contents[offset..] {
+        c if c.trim().is_empty() || given_skip_lines && !c.lines().next().is_some_and(is_header) => {
+            Err(format!("No CSV header row found after skipping {} lines", skip_lines).into())
+        }
+        c => Ok(c),
+    }
+}
+
+/// Reads all rows of a Swedbank CSV, skipping any metadata lines before the header row
+fn read_rows<R: Read>(mut csv: R, skip_lines: Option<usize>) -> Result<Vec<SwedbankCsv>, Box<dyn Error>> {
+    let mut contents = String::new();
+    csv.read_to_string(&mut contents)?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(b';')
+        .from_reader(strip_preamble(&contents, skip_lines)?.as_bytes());
+    let rows = rdr.deserialize().collect::<Result<Vec<SwedbankCsv>, csv::Error>>()?;
+    let needs_amount = |r: &SwedbankCsv| matches!(r.record_type, RecordType::Transaction | RecordType::Interest);
+    if let Some(row) = rows.iter().find(|r| needs_amount(r) && row_amount(r).is_none()) {
+        return Err(format!(
+            "CSV row {} dated {} has no amount or no debit/credit indicator",
+            row.transaction_id, row.date
+        )
+        .into());
+    }
+    Ok(rows)
+}
+
+/// Reads transactions and balances from a Swedbank CSV into a statement for each of the given
+/// currency and account id pairs. Also returns the number of skipped transactions in other currencies.
+fn read_statements<R: Read>(
+    csv: R,
+    accounts: &[(String, String)],
+    options: &ImportOptions,
+) -> Result<(Vec<Statement>, usize), Box<dyn Error>> {
+    let mut statements = accounts
+        .iter()
+        .map(|_| Statement::default())
+        .collect::<Vec<Statement>>();
+    let mut skipped = 0;
+    let mut normalized_currencies: HashSet<String> = HashSet::new();
+
+    for record in read_rows(csv, options.skip_lines)? {
+        if options.dump_raw {
+            eprintln!("{:?}", record);
+        }
+        let record_currency = normalize_currency(&record.currency);
+        if record_currency != record.currency && normalized_currencies.insert(record.currency.clone()) {
+            warn!("Normalized CSV currency {:?} to {:?}", record.currency, record_currency);
+        }
+        let i = match accounts.iter().position(|(currency, _)| *currency == record_currency) {
+            Some(i) => i,
+            None => {
+                if matches!(record.record_type, RecordType::Transaction) {
+                    skipped += 1;
+                }
+                continue;
+            }
+        };
+        let (account_id, statement) = (&accounts[i].1, &mut statements[i]);
+        match record.record_type {
+            RecordType::Transaction => statement.txns.push(from_transaction_row(record, account_id, options)),
+            RecordType::Interest if options.import_interest => {
+                statement.txns.push(from_interest_row(record, account_id, options))
+            }
+            RecordType::StartBalance => {
+                if let Some(b) = row_balance(&record) {
+                    statement.start_balance = b
+                }
+            }
+            RecordType::EndBalance => {
+                if let Some(b) = row_balance(&record) {
+                    statement.end_balance = b
+                }
+                statement.end_date = Some(fmt_date(&record.date));
+            }
+            RecordType::Turnover => {
+                let amount = parse_i64_string(&record.amount).map(i64::abs);
+                match record.debit_or_credit {
+                    Some(EntryType::Debit) if amount.is_some() => statement.debit_turnover = amount,
+                    Some(EntryType::Credit) if amount.is_some() => statement.credit_turnover = amount,
+                    _ => {
+                        // Exports with separate amount columns can have both turnovers on the same row
+                        if let Some(debit) = record.debit_amount.as_deref().and_then(parse_i64_string) {
+                            statement.debit_turnover = Some(debit.abs());
+                        }
+                        if let Some(credit) = record.credit_amount.as_deref().and_then(parse_i64_string) {
+                            statement.credit_turnover = Some(credit.abs());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for statement in statements.iter_mut() {
+        number_duplicate_ids(&mut statement.txns);
+    }
+    Ok((statements, skipped))
+}
+
+/// Parses `KEY = value` pairs, one pair per line. Empty lines and lines starting with # are ignored.
+/// `name` and `format` describe the file and its lines in errors.
+fn parse_key_values<'a>(
+    contents: &'a str,
+    name: &str,
+    format: &str,
+) -> Result<Vec<(&'a str, &'a str)>, Box<dyn Error>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| match l.rsplit_once('=') {
+            Some((key, value)) => Ok((key.trim(), value.trim())),
+            None => Err(format!("Invalid {} line, expected {}: {}", name, format, l).into()),
+        })
+        .collect()
+}
+
+/// Reads currency to account id mappings from a file with `CUR = account_id` lines
+fn read_currency_map(path: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_currency_map(&contents)
+}
+
+fn parse_currency_map(contents: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    Ok(parse_key_values(contents, "currency map", "CUR = account_id")?
+        .into_iter()
+        .map(|(currency, account_id)| (normalize_currency(currency), String::from(account_id)))
+        .collect())
+}
+
+/// Reads payee name to YNAB payee id mappings from a file with `Payee name = payee_id` lines
+fn read_payee_map(path: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_payee_map(&contents)
+}
+
+fn parse_payee_map(contents: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    parse_key_values(contents, "payee map", "Payee name = payee_id")?
+        .into_iter()
+        .map(|(payee, payee_id)| {
+            if is_uuid(payee_id) {
+                Ok((String::from(payee), String::from(payee_id)))
+            } else {
+                Err(format!("Invalid payee id for {}: {}", payee, payee_id).into())
+            }
+        })
+        .collect()
+}
+
+/// Sets the YNAB payee id of transactions with a mapped payee. Their payee name is not sent,
+/// so that YNAB does not match it to some other payee.
+fn apply_payee_ids(txns: &mut [YnabTransaction], payee_ids: &HashMap<String, String>) {
+    for t in txns.iter_mut() {
+        if let Some(payee_id) = payee_ids.get(&t.payee_name) {
+            t.payee_id = Some(payee_id.clone());
+            t.payee_name = String::new();
+        }
+    }
+}
+
+/// Transactions of a single YNAB account, combined from all the imported statements
+struct AccountImport {
+    currency: String,
+    client: YnabClient,
+    txns: Vec<YnabTransaction>,
+    start_balance: i64,
+    end_balance: i64,
+    end_date: Option<String>,
+}
+
+/// Removes transactions with an already seen import id, returns the number of removed transactions
+fn dedup_transactions(txns: &mut Vec<YnabTransaction>) -> usize {
+    let mut import_ids: HashSet<String> = HashSet::new();
+    let count = txns.len();
+    txns.retain(|t| import_ids.insert(t.import_id.clone()));
+    count - txns.len()
+}
+
+/// Compares the YNAB account balance to the CSV balance after the import
+fn check_balance(account: &AccountImport, options: &ImportOptions) -> Result<(), Box<dyn Error>> {
+    let client = &account.client;
+    let (label, expected_balance) = match options.balance_check {
+        BalanceCheck::EndBalance => ("Final CSV balance", account.end_balance),
+        BalanceCheck::StartBalance => (
+            "Starting CSV balance with imported transactions",
+            balance_from_start(account.start_balance, &account.txns),
+        ),
+    };
+
+    let ynab_balance = client.get_acccount_balance()? / 10;
+    if ynab_balance != expected_balance {
+        match options.balance_check {
+            BalanceCheck::EndBalance => println!("== Warning: end balance mismatch:"),
+            BalanceCheck::StartBalance => println!("== Warning: start balance mismatch:"),
+        }
+        println!("{}: {}", label, expected_balance as f32 / 100.0);
+        println!("Current YNAB balance: {}", ynab_balance as f32 / 100.0);
+        println!("Difference: {}", (ynab_balance - expected_balance) as f32 / 100.0);
+
+        if options.create_adjustment {
+            match &account.end_date {
+                Some(date) => {
+                    let adjustment = adjustment_transaction(
+                        &client.account_id,
+                        date,
+                        expected_balance - ynab_balance,
+                        &options.cleared,
+                    );
+                    println!(
+                        "Creating balance adjustment of {} on {}",
+                        adjustment.amount as f32 / 1000.0,
+                        adjustment.date
+                    );
+                    client.post_transactions([adjustment])?;
+                }
+                None => println!("End balance date not found in CSV, balance adjustment not created"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run(csv_paths: &[&str], client: YnabClient, options: &ImportOptions) -> Result<(), Box<dyn Error>> {
+    // Without a currency map, transactions in the budget currency are imported into the given account
+    let accounts = if options.currency_map.is_empty() {
+        vec![(
+            normalize_currency(&client.get_budget_currency()?),
+            client.account_id.clone(),
+        )]
+    } else {
+        options.currency_map.clone()
+    };
+    let multiple_accounts = accounts.len() > 1;
+    let mut statements: Vec<Vec<Statement>> = accounts.iter().map(|_| Vec::new()).collect();
+    let mut unmapped_count = 0;
+
+    // Commissions are rolled up within each statement, before transactions from all the statements are combined
+    for path in csv_paths {
+        let (file_statements, skipped) = read_statements(
+            File::open(path).map_err(|e| format!("{}: {}", path, e))?,
+            &accounts,
+            options,
+        )?;
+        unmapped_count += skipped;
+
+        let mut file_count = 0;
+        for (account_statements, mut statement) in statements.iter_mut().zip(file_statements) {
+            if options.turnover_check {
+                let (debit, credit) = turnover(&statement.txns);
+                check_turnover("debit", statement.debit_turnover, debit);
+                check_turnover("credit", statement.credit_turnover, credit);
+            }
+
+            statement.rolled_up_fees = apply_commissions(&mut statement.txns, options);
+            file_count += statement.txns.len();
+            account_statements.push(statement);
+        }
+
+        if csv_paths.len() > 1 {
+            println!("{}: {} transactions", path, file_count);
+        }
+    }
+
+    if !options.currency_map.is_empty() && unmapped_count > 0 {
+        println!(
+            "{} transactions in currencies without a mapped account skipped",
+            unmapped_count
+        );
+    }
+
+    let ledger = match &options.ledger_file {
+        Some(path) => read_ledger(path)?,
+        None => Ledger::new(),
+    };
+
+    let mut imports: Vec<AccountImport> = Vec::new();
+    for ((currency, account_id), account_statements) in accounts.into_iter().zip(statements) {
+        if multiple_accounts {
+            println!("== {}", currency);
+        }
+
+        let (fee_count, fee_total) = account_statements.iter().fold((0, 0), |(count, total), s| {
+            (count + s.rolled_up_fees.0, total + s.rolled_up_fees.1)
+        });
+        if fee_count > 0 {
+            println!(
+                "{} fees totaling {:.2} {} rolled into their transactions",
+                fee_count,
+                fee_total.abs() as f64 / 1000.0,
+                currency
+            );
+        }
+
+        // Statements are expected to be in chronological order
+        let mut start_balance = account_statements.first().map_or(0, |s| s.start_balance);
+        let (end_balance, end_date) = account_statements
+            .last()
+            .map_or((0, None), |s| (s.end_balance, s.end_date.clone()));
+
+        let mut txns = account_statements
+            .into_iter()
+            .flat_map(|s| s.txns)
+            .collect::<Vec<YnabTransaction>>();
+        let duplicate_count = dedup_transactions(&mut txns);
+        if csv_paths.len() > 1 {
+            println!(
+                "{} transactions in total, {} duplicates across files skipped",
+                txns.len(),
+                duplicate_count
+            );
+        } else if duplicate_count > 0 {
+            println!("{} transactions with duplicate import ids skipped", duplicate_count);
+        }
+
+        if let Some(imported) = ledger.get(&account_id) {
+            let skipped = skip_imported_transactions(&mut txns, imported);
+            if !skipped.is_empty() {
+                println!("{} already imported transactions skipped", skipped.len());
+            }
+            // Skipped transactions are already in YNAB, so they still count towards the expected balance
+            start_balance = balance_from_start(start_balance, &skipped);
+        }
+
+        if let Some(min_amount) = options.min_amount {
+            let skipped = skip_small_transactions(&mut txns, min_amount);
+            if skipped > 0 {
+                println!("{} transactions below the minimum amount skipped", skipped);
+            }
+        }
+
+        if let Some(max_age) = options.max_age {
+            if let Some(age) = statement_age(&txns, days_since_epoch_today()).filter(|&a| a > max_age) {
+                if options.strict {
+                    return Err(
+                        format!("Newest transaction in the CSV is {} days old, refusing to import", age).into(),
+                    );
+                }
+                println!("== Warning: newest transaction in the CSV is {} days old", age);
+            }
+        }
+
+        if options.flag_possible_duplicates {
+            let flagged = flag_possible_duplicates(&mut txns);
+            if flagged > 0 {
+                println!("{} possible duplicate transactions flagged", flagged);
+            }
+        }
+
+        imports.push(AccountImport {
+            currency,
+            client: client.with_account(&account_id)?,
+            txns,
+            start_balance,
+            end_balance,
+            end_date,
+        });
+    }
+
+    if options.diff {
+        for import in imports.iter() {
+            if multiple_accounts {
+                println!("== {}", import.currency);
+            }
+            print_diff(&import.txns, &import.client)?;
+        }
+        if options.print_unmatched {
+            print_unmatched_payees(&imports);
+        }
+        return Ok(());
+    }
+
+    if !options.payee_ids.is_empty() {
+        for import in imports.iter_mut() {
+            apply_payee_ids(&mut import.txns, &options.payee_ids);
+        }
+    }
+
+    if let Some(path) = &options.export_path {
+        export_transactions(path, imports.iter().flat_map(|i| i.txns.iter()))?;
+    }
+
+    let count = imports.iter().map(|i| i.txns.len()).sum();
+    let account_uris = imports
+        .iter()
+        .map(|i| i.client.app_account_uri())
+        .collect::<Vec<String>>()
+        .join(", ");
+    if needs_confirmation(count, options.confirm_threshold) && !confirm_import(count, &account_uris)? {
+        println!("Import cancelled");
+        return Ok(());
+    }
+
+    // All accounts are posted before anything else is done, so that in atomic mode a failure
+    // in any of the accounts rolls back the transactions of all of them
+    let mut results: Vec<PostResult> = Vec::new();
+    let mut failure = None;
+    for import in imports.iter() {
+        match post_chunks(
+            &import.client,
+            &import.txns,
+            options.chunk_order,
+            options.atomic,
+            options.concurrency,
+        ) {
+            Ok(result) => results.push(result),
+            Err(err) => {
+                let posted_ids = results
+                    .iter()
+                    .flat_map(|r| r.transaction_ids.iter().cloned())
+                    .collect::<Vec<String>>();
+                if options.atomic {
+                    if !posted_ids.is_empty() {
+                        rollback_import(&import.client, &posted_ids);
+                    }
+                    return Err(err);
+                }
+                failure = Some(err);
+                break;
+            }
+        }
+    }
+
+    let mut latest_knowledge = None;
+    for (import, result) in imports.iter().zip(results) {
+        if multiple_accounts {
+            println!("== {}", import.currency);
+        }
+
+        let PostResult {
+            imported,
+            duplicates,
+            server_knowledge,
+            ..
+        } = result;
+        latest_knowledge = latest_knowledge.max(server_knowledge);
+
+        println!("{} new transactions imported", imported);
+        println!("{} duplicates found", duplicates);
+
+        if imported > 0 {
+            println!("See new transactions in app: {}", import.client.app_account_uri());
+        }
+
+        if let Some(path) = &options.ledger_file {
+            save_ledger(path, &import.client.account_id, &import.txns)?;
+        }
+
+        if failure.is_none() {
+            check_balance(import, options)?;
+        }
+    }
+
+    // Accounts posted before the failure are reported and saved in the ledger above
+    if let Some(err) = failure {
+        return Err(err);
+    }
+
+    if options.print_unmatched {
+        print_unmatched_payees(&imports);
+    }
+
+    if let (Some(path), Some(knowledge)) = (&options.knowledge_file, latest_knowledge) {
+        save_server_knowledge(path, &client.budget_id, knowledge)?;
+    }
+
+    Ok(())
+}
+
+/// Runs the given shell command and returns its output
+fn token_command_output(cmd: &str) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+    if !output.status.success() {
+        return Err(format!("Token command failed: {}", output.status).into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Returns the YNAB token from a token file, a token command or the token option, in that order
+fn resolve_token(
+    token_file: Option<&str>,
+    token_command: Option<&str>,
+    token: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let token = match (token_file, token_command) {
+        (Some(path), _) => std::fs::read_to_string(path).map_err(|e| format!("Token file {}: {}", path, e))?,
+        (None, Some(cmd)) => token_command_output(cmd)?,
+        (None, None) => token.map(String::from).unwrap_or_default(),
+    };
+
+    match token.trim() {
+        "" => Err("No YNAB token provided, use -t, --token-file or --token-command".into()),
+        t => Ok(String::from(t)),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    env_logger::init();
+    let args = App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("import-json")
+                .about("Imports transactions from a file written with --export-json")
+                .arg(
+                    Arg::with_name("JSON_PATH")
+                        .help("Path for exported JSON transactions")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks that the access token, budget and account work, without importing anything"),
+        )
+        .arg(
+            Arg::with_name("CSV_PATH")
+                .help("Paths for Swedbank CSV exports, in chronological order")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("token")
+                .short("t")
+                .env("YNAB_TOKEN")
+                .value_name("TOKEN")
+                .help("YNAB personal acces token"),
+        )
+        .arg(
+            Arg::with_name("token-file")
+                .long("token-file")
+                .value_name("PATH")
+                .conflicts_with("token-command")
+                .help("Read YNAB personal access token from a file"),
+        )
+        .arg(
+            Arg::with_name("token-command")
+                .long("token-command")
+                .value_name("COMMAND")
+                .help("Read YNAB personal access token from the output of a command"),
+        )
+        .arg(
+            Arg::with_name("budget")
+                .short("b")
+                .required_unless("preview-payees")
+                .env("YNAB_BUDGET")
+                .value_name("BUDGET")
+                .help("YNAB budget id"),
+        )
+        .arg(
+            Arg::with_name("account")
+                .short("a")
+                .required_unless_one(&["currency-map", "preview-payees"])
+                .env("YNAB_ACCOUNT")
+                .value_name("ACCOUNT")
+                .help("YNAB account id"),
+        )
+        .arg(
+            Arg::with_name("currency-map")
+                .long("currency-map")
+                .value_name("PATH")
+                .help("File with CUR = ACCOUNT lines for importing each currency into its own account"),
+        )
+        .arg(
+            Arg::with_name("payee-map")
+                .long("payee-map")
+                .value_name("PATH")
+                .help("File with PAYEE = PAYEE_ID lines for posting given payees by their YNAB payee id"),
+        )
+        .arg(
+            Arg::with_name("cleared")
+                .long("cleared")
+                .value_name("STATE")
+                .possible_values(CLEARED_STATES)
+                .default_value("cleared")
+                .help("Cleared state of imported transactions"),
+        )
+        .arg(
+            Arg::with_name("cleared-map")
+                .long("cleared-map")
+                .value_name("PAYMENT_TYPE=STATE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Cleared state of transactions of given payment type, e.g. CTX=uncleared"),
+        )
+        .arg(
+            Arg::with_name("default-payee")
+                .long("default-payee")
+                .value_name("PAYEE")
+                .default_value(DEFAULT_PAYEE)
+                .help("Payee for transactions that have no payee in the CSV"),
+        )
+        .arg(
+            Arg::with_name("fee-category")
+                .long("group-fees-into-category")
+                .value_name("CATEGORY")
+                .help("Import commissions as separate transactions in the given YNAB category id"),
+        )
+        .arg(
+            Arg::with_name("dump-raw")
+                .long("dump-raw")
+                .help("Print parsed CSV rows to stderr, e.g. for bug reports"),
+        )
+        .arg(
+            Arg::with_name("flag-possible-dupes")
+                .long("flag-possible-dupes")
+                .help("Flag transactions with the same date, payee and amount for review"),
+        )
+        .arg(
+            Arg::with_name("no-rollup")
+                .long("no-rollup")
+                .conflicts_with("fee-category")
+                .help("Import commissions as separate transactions"),
+        )
+        .arg(
+            Arg::with_name("chunk-order")
+                .long("chunk-order")
+                .value_name("ORDER")
+                .possible_values(&["forward", "reverse"])
+                .default_value("reverse")
+                .help("Order in which CSV rows are posted to YNAB"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .value_name("COUNT")
+                .default_value("4")
+                .help("Maximum number of chunks posted to YNAB at the same time, started in --chunk-order"),
+        )
+        .arg(
+            Arg::with_name("max-age")
+                .long("max-age")
+                .value_name("DAYS")
+                .help("Warn if the newest transaction in the CSV is older than given number of days"),
+        )
+        .arg(
+            Arg::with_name("min-amount")
+                .long("min-amount")
+                .value_name("AMOUNT")
+                .help("Skip transactions smaller than the given amount, e.g. 0.50"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .requires("max-age")
+                .help("Refuse to import CSVs that are older than --max-age"),
+        )
+        .arg(
+            Arg::with_name("knowledge-file")
+                .long("knowledge-file")
+                .value_name("PATH")
+                .help("JSON file where the latest YNAB server knowledge of the budget is stored"),
+        )
+        .arg(
+            Arg::with_name("ledger")
+                .long("ledger")
+                .value_name("PATH")
+                .help("JSON file of imported transaction ids, known transactions are not posted again"),
+        )
+        .arg(
+            Arg::with_name("rollup-suffix")
+                .long("rollup-suffix")
+                .value_name("SUFFIX")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Additional memo suffix of commissions that are added to their transactions"),
+        )
+        .arg(
+            Arg::with_name("confirm-threshold")
+                .long("confirm-threshold")
+                .value_name("COUNT")
+                .default_value("100")
+                .help("Ask for confirmation before importing more than given number of transactions"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Import without asking for confirmation"),
+        )
+        .arg(
+            Arg::with_name("merge-memo-payee")
+                .long("merge-memo-payee")
+                .value_name("PAYMENT_TYPE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Combine CSV payee and memo into the memo for rows of given payment type"),
+        )
+        .arg(
+            Arg::with_name("merged-payee")
+                .long("merged-payee")
+                .value_name("PAYEE")
+                .default_value("Transfer")
+                .help("Payee for rows with combined payee and memo"),
+        )
+        .arg(
+            Arg::with_name("atomic")
+                .long("atomic")
+                .help("Delete already imported transactions if the import fails"),
+        )
+        .arg(
+            Arg::with_name("skip-lines")
+                .long("skip-lines")
+                .value_name("COUNT")
+                .help("Number of lines before the CSV header row, detected automatically by default"),
+        )
+        .arg(
+            Arg::with_name("source-tag")
+                .long("source-tag")
+                .value_name("TAG")
+                .help("Tag appended to the memo of every imported transaction, e.g. #swedbank-eur"),
+        )
+        .arg(
+            Arg::with_name("memo-format")
+                .long("memo-format")
+                .value_name("TEMPLATE")
+                .default_value(DEFAULT_MEMO_FORMAT)
+                .help("Template of imported memos, placeholders: {memo} {reference} {merchant} {fx} {type} {source}"),
+        )
+        .arg(
+            Arg::with_name("import-interest")
+                .long("import-interest")
+                .help("Import interest rows as transactions"),
+        )
+        .arg(
+            Arg::with_name("preview-payees")
+                .long("preview-payees")
+                .help("List CSV payees and the payees they are imported as, without importing anything"),
+        )
+        .arg(
+            Arg::with_name("print-unmatched-payees")
+                .long("print-unmatched-payees")
+                .help("List CSV payees that did not match any known payee formats"),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .help("List transactions that are not yet in YNAB, without importing anything"),
+        )
+        .arg(
+            Arg::with_name("check-turnover")
+                .long("check-turnover")
+                .help("Check imported debits and credits against CSV turnover"),
+        )
+        .arg(
+            Arg::with_name("create-adjustment")
+                .long("create-adjustment")
+                .help("Create a balance adjustment transaction when balances do not match"),
+        )
+        .arg(
+            Arg::with_name("check-start-balance")
+                .long("check-start-balance")
+                .help("Check YNAB balance against CSV start balance instead of end balance"),
+        )
+        .arg(
+            Arg::with_name("export-json")
+                .long("export-json")
+                .value_name("PATH")
+                .help("Write transactions to a JSON file before importing them"),
+        )
+        .get_matches();
+
+    let currency_map = match args.value_of("currency-map").map(read_currency_map).transpose() {
+        Ok(currency_map) => currency_map.unwrap_or_default(),
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let payee_ids = match args.value_of("payee-map").map(read_payee_map).transpose() {
+        Ok(payee_ids) => payee_ids.unwrap_or_default(),
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let balance_check = if args.is_present("check-start-balance") {
+        BalanceCheck::StartBalance
+    } else {
+        BalanceCheck::EndBalance
+    };
+
+    let chunk_order = match args.value_of("chunk-order") {
+        Some("forward") => ChunkOrder::Forward,
+        _ => ChunkOrder::Reverse,
+    };
+
+    let max_age = match args.value_of("max-age").map(|a| a.parse::<i64>()) {
+        Some(Ok(days)) => Some(days),
+        Some(Err(_)) => {
+            println!("Invalid --max-age value, expected number of days");
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    let min_amount = match args.value_of("min-amount").map(parse_major_units) {
+        Some(Some(amount)) => Some(amount),
+        Some(None) => {
+            println!("Invalid --min-amount value, expected an amount, e.g. 0.50");
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    // Prompt is skipped when confirmed upfront or when not running interactively
+    let confirm_threshold = match args.value_of("confirm-threshold").map(|t| t.parse::<usize>()) {
+        _ if args.is_present("yes") || !io::stdin().is_terminal() => None,
+        Some(Ok(threshold)) => Some(threshold),
+        _ => {
+            println!("Invalid --confirm-threshold value, expected number of transactions");
+            process::exit(1);
+        }
+    };
+
+    let cleared_map = match args
+        .values_of("cleared-map")
+        .map_or(Some(HashMap::new()), |v| v.map(parse_cleared_mapping).collect())
+    {
+        Some(cleared_map) => cleared_map,
+        None => {
+            println!("Invalid --cleared-map value, expected PAYMENT_TYPE=STATE, e.g. CTX=uncleared");
+            process::exit(1);
+        }
+    };
+
+    let skip_lines = match args.value_of("skip-lines").map(|l| l.parse::<usize>()) {
+        Some(Ok(lines)) => Some(lines),
+        Some(Err(_)) => {
+            println!("Invalid --skip-lines value, expected number of lines");
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    let memo_format = String::from(args.value_of("memo-format").unwrap());
+    if let Err(err) = validate_memo_format(&memo_format) {
+        println!("Invalid --memo-format value, {}", err);
+        process::exit(1);
+    }
+
+    let concurrency = match args.value_of("concurrency").map(|c| c.parse::<usize>()) {
+        Some(Ok(concurrency)) if concurrency > 0 => concurrency,
+        _ => {
+            println!("Invalid --concurrency value, expected a positive number of requests");
+            process::exit(1);
+        }
+    };
+
+    let options = ImportOptions {
+        balance_check,
+        cleared: String::from(args.value_of("cleared").unwrap()),
+        cleared_map,
+        default_payee: String::from(args.value_of("default-payee").unwrap()),
+        diff: args.is_present("diff"),
+        fee_category: args.value_of("fee-category").map(String::from),
+        no_rollup: args.is_present("no-rollup"),
+        chunk_order,
+        max_age,
+        strict: args.is_present("strict"),
+        knowledge_file: args.value_of("knowledge-file").map(String::from),
+        turnover_check: args.is_present("check-turnover"),
+        rollup_suffixes: args
+            .values_of("rollup-suffix")
+            .map_or(vec![], |v| v.map(String::from).collect()),
+        confirm_threshold,
+        merge_payment_types: args
+            .values_of("merge-memo-payee")
+            .map_or(vec![], |v| v.map(String::from).collect()),
+        merged_payee: String::from(args.value_of("merged-payee").unwrap()),
+        atomic: args.is_present("atomic"),
+        source_tag: args.value_of("source-tag").map(String::from),
+        memo_format,
+        import_interest: args.is_present("import-interest"),
+        print_unmatched: args.is_present("print-unmatched-payees"),
+        create_adjustment: args.is_present("create-adjustment"),
+        export_path: args.value_of("export-json").map(String::from),
+        min_amount,
+        currency_map,
+        flag_possible_duplicates: args.is_present("flag-possible-dupes"),
+        dump_raw: args.is_present("dump-raw"),
+        skip_lines,
+        ledger_file: args.value_of("ledger").map(String::from),
+        concurrency,
+        payee_ids,
+    };
+
+    let csv_paths = args.values_of("CSV_PATH").map_or(vec![], |v| v.collect::<Vec<&str>>());
+
+    if args.is_present("preview-payees") {
+        if let Err(err) = preview_payees(&csv_paths, &options) {
+            println!("{}", err);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // With a currency map, the account id is only used for a client in the right budget
+    let account_id = args
+        .value_of("account")
+        .or(options.currency_map.first().map(|(_, account_id)| account_id.as_str()))
+        .unwrap_or("");
+
+    let client = match resolve_token(
+        args.value_of("token-file"),
+        args.value_of("token-command"),
+        args.value_of("token"),
+    )
+    .and_then(|token| {
+        YnabClient::new(
+            args.value_of("budget").unwrap_or("").to_string(),
+            account_id.to_string(),
+            &token,
+        )
+    }) {
+        Ok(client) => client,
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    if args.subcommand_matches("check").is_some() {
+        if let Err(err) = check(&client) {
+            println!("{}", err);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(import_args) = args.subcommand_matches("import-json") {
+        if let Err(err) = import_json(import_args.value_of("JSON_PATH").unwrap(), client, &options) {
+            println!("{}", err);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Err(err) = run(&csv_paths, client, &options) {
+        println!("{}", err);
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockServer, Request};
+    use std::sync::Arc;
+
+    static BUDGET_ID: &str = "01234567-89ab-cdef-0123-456789abcdef";
+    static ACCOUNT_ID: &str = "fedcba98-7654-3210-fedc-ba9876543210";
+
+    fn test_client(server: &MockServer) -> YnabClient {
+        YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token")
+            .unwrap()
+            .with_api_url(&server.url)
+    }
+
+    fn test_row(payee: &str, memo: &str, payment_type: &str) -> SwedbankCsv {
+        SwedbankCsv {
+            record_type: RecordType::Transaction,
+            date: String::from("09.02.2020"),
+            payee: String::from(payee),
+            memo: String::from(memo),
+            amount: String::from("5,00"),
+            currency: String::from("EUR"),
+            debit_or_credit: Some(EntryType::Debit),
+            debit_amount: None,
+            credit_amount: None,
+            transaction_id: String::from("123"),
+            payment_type: String::from(payment_type),
+        }
+    }
+
+    #[test]
+    fn test_absent_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("", "Payment").payee, "Swedbank");
+    }
+
+    #[test]
+    fn test_absent_payee_override() {
+        assert_eq!(ParsedPayeeMemo::with_default_payee("", "Payment", "Bank").payee, "Bank");
+    }
+
+    #[test]
+    fn test_basic_cc_payment() {
+        let r = ParsedPayeeMemo::from_str("Abc", "PIRKUMS 0***1 28.12.2021 5.00 EUR (123456) Abc");
+        assert_eq!(None, r.memo);
+        assert_eq!(String::from("Abc"), r.payee);
+    }
+
+    #[test]
+    fn test_foreign_currency_cc_payment() {
+        let r = ParsedPayeeMemo::from_str(
+            "Abc",
+            "PIRKUMS 0******1 30.07.24 13:07 24.90 CHF, ATTIECĪBĀ PRET ECB VALŪTAS KURSU 2.3% (123456) Abc",
+        );
+        assert_eq!(None, r.memo);
+        assert_eq!(String::from("Abc"), r.payee);
+    }
+
+    #[test]
+    fn test_foreign_currency_cc_payment_date() {
+        let row = test_row(
+            "Abc",
+            "PIRKUMS 0******1 30.07.24 13:07 24.90 CHF, ATTIECĪBĀ PRET ECB VALŪTAS KURSU 2.3% (123456) Abc",
+            "CTX",
+        );
+        assert_eq!(
+            from_transaction_row(row, "account", &ImportOptions::default()).date,
+            String::from("2024-07-30")
+        );
+    }
+
+    #[test]
+    fn test_sumup_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("SumUp", "SumUp  *Foobar 1").payee, "Foobar 1");
+    }
+
+    #[test]
+    fn test_sumup_payee2() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("SumUp", "PIRKUMS 0***1 28.12.2021 5.00 EUR (123456) SumUp  *Abc").payee,
+            "Abc"
+        );
+    }
+
+    #[test]
+    fn test_izettle_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("IZ *Payee222", "memo!").payee, "Payee222");
+    }
+
+    #[test]
+    fn test_zettle_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("Zettle_*Cafe", "memo!").payee, "Cafe");
+    }
+
+    #[test]
+    fn test_lowercase_izettle_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("iZ *Cafe", "memo!").payee, "Cafe");
+    }
+
+    #[test]
+    fn test_gumroad_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("GUM.CO/CC* Gumroad1", "memo!").payee,
+            "Gumroad1"
+        );
+    }
+
+    #[test]
+    fn test_padded_memo_repeating_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("Shop", " Shop ").memo, None);
+    }
+
+    #[test]
+    fn test_payee_preview() {
+        let txns = vec![
+            from_transaction_row(
+                test_row("AMZN Mktp DE*AB12", "", "CTX"),
+                "account",
+                &ImportOptions::default(),
+            ),
+            from_transaction_row(
+                test_row("AMZN Mktp DE*CD34", "", "CTX"),
+                "account",
+                &ImportOptions::default(),
+            ),
+            from_transaction_row(
+                test_row("AMZN Mktp DE*CD34", "", "CTX"),
+                "account",
+                &ImportOptions::default(),
+            ),
+            from_transaction_row(test_row("Corner Shop", "", "CTX"), "account", &ImportOptions::default()),
+        ];
+        assert_eq!(
+            payee_preview(&txns),
+            vec![
+                ("AMZN Mktp DE*CD34", "Amazon Marketplace", true, 2),
+                ("AMZN Mktp DE*AB12", "Amazon Marketplace", true, 1),
+                ("Corner Shop", "Corner Shop", false, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_amazon_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("AMZN Digital*Foo 111", "memo!").payee,
+            "Amazon Digital"
+        );
+    }
+
+    #[test]
+    fn test_amazon_prime_payee() {
+        let parsed = ParsedPayeeMemo::from_str("Amazon Prime*AB12CD34E", "Amazon Prime*AB12CD34E Prime membership");
+        assert_eq!(parsed.payee, "Amazon Prime");
+        assert_eq!(parsed.memo, Some(String::from("AB12CD34E Prime membership")));
+    }
+
+    #[test]
+    fn test_amazon_marketplace_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("AMZN Mktp DE*AB12CD34E", "memo!").payee,
+            "Amazon Marketplace"
+        );
+    }
+
+    #[test]
+    fn test_unknown_amazon_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("AMZN*AB12CD34E", "memo!").payee, "Amazon");
+    }
+
+    #[test]
+    fn test_kindle_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("Kindle Svcs*0F00T0000 00000 000-000-0000", "memo!").payee,
+            "Kindle Svcs"
+        );
+    }
+
+    #[test]
+    fn test_patreon_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("Patreon* Membership", "memo!").payee,
+            "Patreon"
+        );
+    }
+
+    #[test]
+    fn test_airbnb_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("AIRBNB * FOOBAR 000 999-101-1111", "memo!").payee,
+            "AIRBNB"
+        );
+    }
+
+    #[test]
+    fn test_matched_payee() {
+        assert!(ParsedPayeeMemo::from_str("AIRBNB * FOOBAR 000 999-101-1111", "memo!").matched);
+        assert!(ParsedPayeeMemo::from_str("PAYPAL *foobar L2449 00000000000", "memo!").matched);
+    }
+
+    #[test]
+    fn test_unmatched_payee() {
+        assert!(!ParsedPayeeMemo::from_str("Foobar", "memo!").matched);
+        assert!(!ParsedPayeeMemo::from_str("GUM.CO/CC* Gumroad1", "memo!").matched);
+    }
+
+    #[test]
+    fn test_escapable_payee() {
+        assert_eq!(ParsedPayeeMemo::from_str("'Foobar", "Test").payee, "Foobar");
+    }
+
+    #[test]
+    fn test_revolut_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str(
+                "Revolut**1234* D02 R296 Dublin",
+                "PIRKUMS 123******1234 01.08.2023 10.00 EUR (123) Revolut**1234* D02 R296 Dublin"
             )
             .payee,
             "Revolut"
@@ -348,20 +2280,1234 @@ mod tests {
     }
 
     #[test]
-    fn test_paysera_payee() {
+    fn test_revolut_top_up_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str("Revolut Ltd", "Top-Up by *1234").payee,
+            "Revolut top-up"
+        );
+    }
+
+    #[test]
+    fn test_paysera_payee() {
+        assert_eq!(
+            ParsedPayeeMemo::from_str(
+                "Paysera LT",
+                "R000 Pasutijums Nr. 14, projekts https://www.kartes.lv pardevejs: Jana seta"
+            )
+            .payee,
+            "Jana seta"
+        );
+    }
+
+    fn test_transaction(import_id: &str, amount: i64) -> YnabTransaction {
+        YnabTransaction {
+            import_id: String::from(import_id),
+            date: String::from("2020-02-09"),
+            payee_name: String::from("Foo"),
+            memo: None,
+            cleared: String::from("cleared"),
+            amount,
+            account_id: String::from("account"),
+            category_id: None,
+            flag_color: None,
+            payee_id: None,
+            needs_rollup: false,
+            raw_payee: String::from("Foo"),
+            payee_matched: true,
+        }
+    }
+
+    #[test]
+    fn test_balance_from_start() {
+        let txns = vec![test_transaction("1", -12990), test_transaction("2", 490)];
+        assert_eq!(balance_from_start(10000, &txns), 8750);
+    }
+
+    #[test]
+    fn test_adjustment_transaction() {
+        let txn = adjustment_transaction("account", "2020-02-29", -1250, "cleared");
+        assert_eq!(txn.amount, -12500);
+        assert_eq!(txn.date, String::from("2020-02-29"));
+        assert_eq!(txn.payee_name, String::from("Balance Adjustment"));
+        assert_eq!(txn.import_id, String::from("ADJ:2020-02-29:-12500"));
+    }
+
+    #[test]
+    fn test_balance_from_start_no_transactions() {
+        assert_eq!(balance_from_start(10000, &[]), 10000);
+    }
+
+    #[test]
+    fn test_cc_payment_date() {
+        let r = ParsedPayeeMemo::from_str("Abc", "PIRKUMS 0***1 28.12.2021 5.00 EUR (123456) Abc");
+        assert_eq!(Some(String::from("28.12.2021")), r.date);
+    }
+
+    #[test]
+    fn test_malformed_cc_payment_date() {
+        let row = test_row("Abc", "PIRKUMS 0***1 Abc", "CTX");
+        assert_eq!(None, ParsedPayeeMemo::from_str(&row.payee, &row.memo).date);
+        assert_eq!(
+            from_transaction_row(row, "account", &ImportOptions::default()).date,
+            String::from("2020-02-09")
+        );
+    }
+
+    #[test]
+    fn test_is_date() {
+        assert!(is_date("09.02.2020"));
+        assert!(is_date("30.07.24"));
+        assert!(!is_date("30.07.224"));
+        assert!(!is_date("Abc"));
+        assert!(!is_date("5.00"));
+    }
+
+    #[test]
+    fn test_reconciled_transaction() {
+        let row = test_row("Abc", "Abc", "CTX");
+        let options = ImportOptions {
+            cleared: String::from("reconciled"),
+            ..ImportOptions::default()
+        };
+        let json = serde_json::to_value(from_transaction_row(row, "account", &options)).unwrap();
+        assert_eq!(json["cleared"], "reconciled");
+    }
+
+    #[test]
+    fn test_diff_transactions() {
+        let txns = vec![test_transaction("1", -12990), test_transaction("2", 490)];
+        let existing = HashSet::from([String::from("2"), String::from("3")]);
+        let (new, existing) = diff_transactions(&txns, &existing);
+        assert_eq!(
+            new.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>(),
+            vec!["1"]
+        );
+        assert_eq!(
+            existing.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>(),
+            vec!["2"]
+        );
+    }
+
+    fn test_fee(import_id: &str, amount: i64) -> YnabTransaction {
+        YnabTransaction {
+            needs_rollup: true,
+            ..test_transaction(import_id, amount)
+        }
+    }
+
+    #[test]
+    fn test_rollup_commissions() {
+        let mut txns = vec![test_transaction("1", -12990), test_fee("1_1", -500)];
+        assert_eq!(rollup_commissions(&mut txns), (1, -500));
+        assert_eq!(txns.len(), 1);
+        assert_eq!(txns[0].amount, -13490);
+    }
+
+    #[test]
+    fn test_parse_major_units() {
+        assert_eq!(parse_major_units("0.50"), Some(500));
+        assert_eq!(parse_major_units("0,5"), Some(500));
+        assert_eq!(parse_major_units("2"), Some(2000));
+        assert_eq!(parse_major_units("foo"), None);
+    }
+
+    #[test]
+    fn test_skip_small_transactions() {
+        let mut txns = vec![
+            test_transaction("1", -300),
+            test_fee("1_1", -500),
+            test_transaction("2", -400),
+            test_fee("2_1", -50),
+            test_transaction("3", 200),
+        ];
+        rollup_commissions(&mut txns);
+        assert_eq!(skip_small_transactions(&mut txns, 500), 2);
+        assert_eq!(txns.len(), 1);
+        assert_eq!(txns[0].import_id, "1");
+        assert_eq!(txns[0].amount, -800);
+    }
+
+    #[test]
+    fn test_no_rollup() {
+        let options = ImportOptions {
+            no_rollup: true,
+            ..ImportOptions::default()
+        };
+        let mut txns = vec![
+            from_transaction_row(test_row("Abc", "Abc", "CTX"), "account", &options),
+            from_transaction_row(
+                test_row("", "Maksājuma apkalpošanas komisija", "KOM"),
+                "account",
+                &options,
+            ),
+        ];
+        apply_commissions(&mut txns, &options);
+        assert_eq!(txns.len(), 2);
+        assert_eq!(txns[0].import_id, "123");
+        assert_eq!(txns[0].amount, -5000);
+        assert_eq!(txns[1].import_id, "123_1");
+        assert_eq!(txns[1].amount, -5000);
+    }
+
+    #[test]
+    fn test_rollup_leading_commission() {
+        let mut txns = vec![test_fee("1_1", -500), test_transaction("2", -1000)];
+        assert_eq!(rollup_commissions(&mut txns), (0, 0));
+        assert_eq!(txns.len(), 2);
+        assert_eq!(txns[0].amount, -500);
+        assert!(!txns[0].needs_rollup);
+    }
+
+    #[test]
+    fn test_multiple_commission_ids() {
+        let mut statement = test_statement(&[
+            "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"",
+            "\"20\";\"09.02.2020\";\"\";\"Maksājuma apkalpošanas komisija\";\"0,50\";\"EUR\";\"D\";\"123\";\"KOM\"",
+            "\"20\";\"09.02.2020\";\"\";\"Maksājuma apkalpošanas komisija\";\"0,25\";\"EUR\";\"D\";\"123\";\"KOM\"",
+        ]);
+        let options = ImportOptions {
+            no_rollup: true,
+            ..ImportOptions::default()
+        };
+        apply_commissions(&mut statement.txns, &options);
+        assert_eq!(dedup_transactions(&mut statement.txns), 0);
+        assert_eq!(
+            statement
+                .txns
+                .iter()
+                .map(|t| t.import_id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["123", "123_1", "123_2"]
+        );
+    }
+
+    #[test]
+    fn test_rollup_commissions_totals() {
+        let mut txns = vec![
+            test_transaction("1", -12990),
+            test_fee("1_1", -500),
+            test_transaction("2", -1000),
+            test_fee("2_1", -400),
+            test_fee("2_2", -300),
+        ];
+        assert_eq!(rollup_commissions(&mut txns), (3, -1200));
+        assert_eq!(txns.len(), 2);
+    }
+
+    #[test]
+    fn test_categorize_fees() {
+        let mut txns = vec![test_transaction("1", -12990), test_fee("1_1", -500)];
+        categorize_fees(&mut txns, "fees");
+        rollup_commissions(&mut txns);
+        assert_eq!(txns.len(), 2);
+        assert_eq!(txns[0].amount, -12990);
+        assert_eq!(txns[0].category_id, None);
+        assert_eq!(txns[1].amount, -500);
+        assert_eq!(txns[1].category_id, Some(String::from("fees")));
+    }
+
+    #[test]
+    fn test_chunk_order() {
+        let txns = vec![
+            test_transaction("1", 100),
+            test_transaction("2", 200),
+            test_transaction("3", 300),
+        ];
+        let ids = |order| {
+            ordered_chunks(&txns, 2, order)
+                .map(|c| c.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>())
+                .collect::<Vec<Vec<&str>>>()
+        };
+        assert_eq!(ids(ChunkOrder::Forward), vec![vec!["1", "2"], vec!["3"]]);
+        assert_eq!(ids(ChunkOrder::Reverse), vec![vec!["2", "3"], vec!["1"]]);
+    }
+
+    #[test]
+    fn test_token_file() {
+        let path = std::env::temp_dir().join("ynab-swed-test-token");
+        std::fs::write(&path, "file-token\n").unwrap();
+        assert_eq!(
+            resolve_token(path.to_str(), None, Some("token")).unwrap(),
+            String::from("file-token")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_token_file() {
+        let path = std::env::temp_dir().join("ynab-swed-test-missing-token");
+        let err = resolve_token(path.to_str(), None, Some("token"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with(&format!("Token file {}: ", path.to_str().unwrap())));
+    }
+
+    #[test]
+    fn test_token_command() {
+        assert_eq!(
+            resolve_token(None, Some("echo command-token"), Some("token")).unwrap(),
+            String::from("command-token")
+        );
+    }
+
+    #[test]
+    fn test_token_option() {
+        assert_eq!(resolve_token(None, None, Some("token")).unwrap(), String::from("token"));
+    }
+
+    #[test]
+    fn test_missing_token() {
+        assert!(resolve_token(None, None, None).is_err());
+        assert!(resolve_token(None, Some("true"), Some("token")).is_err());
+    }
+
+    #[test]
+    fn test_days_since_epoch() {
+        assert_eq!(days_since_epoch("1970-01-01"), Some(0));
+        assert_eq!(days_since_epoch("2000-03-01"), Some(11017));
+        assert_eq!(days_since_epoch("2024-02-29"), Some(19782));
+        assert_eq!(days_since_epoch("2020-2-9"), Some(18301));
+        assert_eq!(days_since_epoch("09.02.2020"), None);
+    }
+
+    #[test]
+    fn test_statement_age() {
+        let txns = vec![
+            YnabTransaction {
+                date: String::from("2020-02-01"),
+                ..test_transaction("1", 100)
+            },
+            YnabTransaction {
+                date: String::from("2020-02-09"),
+                ..test_transaction("2", 100)
+            },
+        ];
+        let today = days_since_epoch("2020-03-10").unwrap();
+        assert_eq!(statement_age(&txns, today), Some(30));
+        assert_eq!(statement_age(&[], today), None);
+    }
+
+    #[test]
+    fn test_save_server_knowledge() {
+        let path = std::env::temp_dir().join("ynab-swed-test-knowledge.json");
+        let path = path.to_str().unwrap();
+        save_server_knowledge(path, "budget1", 10).unwrap();
+        save_server_knowledge(path, "budget2", 20).unwrap();
+        save_server_knowledge(path, "budget1", 30).unwrap();
+
+        let knowledge: HashMap<String, i64> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(knowledge.get("budget1"), Some(&30));
+        assert_eq!(knowledge.get("budget2"), Some(&20));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_skip_imported_transactions() {
+        let mut txns = vec![
+            test_transaction("1", 100),
+            test_transaction("2", 200),
+            test_transaction("3", 300),
+        ];
+        let imported = BTreeSet::from([String::from("1"), String::from("3")]);
+        let skipped = skip_imported_transactions(&mut txns, &imported);
+        assert_eq!(
+            skipped.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>(),
+            vec!["1", "3"]
+        );
+        assert_eq!(
+            txns.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>(),
+            vec!["2"]
+        );
+    }
+
+    #[test]
+    fn test_ledger_import() {
+        let server = MockServer::start(|request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", _) => (
+                201,
+                String::from(r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#),
+            ),
+            ("GET", p) if p.ends_with(BUDGET_ID) => (
+                200,
+                String::from(r#"{"data": {"budget": {"currency_format": {"iso_code": "EUR"}}}}"#),
+            ),
+            _ => (200, String::from(r#"{"data": {"account": {"balance": 0}}}"#)),
+        });
+        let csv_path = std::env::temp_dir().join("ynab-swed-test-ledger.csv");
+        let ledger_path = std::env::temp_dir().join("ynab-swed-test-ledger.json");
+        std::fs::write(
+            &csv_path,
+            format!(
+                "{}\n{}\n{}\n",
+                CSV_HEADER,
+                "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"",
+                "\"20\";\"10.02.2020\";\"Foo\";\"Bar\";\"6,00\";\"EUR\";\"D\";\"124\";\"CTX\""
+            ),
+        )
+        .unwrap();
+        std::fs::write(&ledger_path, format!(r#"{{"{}": ["123"]}}"#, ACCOUNT_ID)).unwrap();
+        let options = ImportOptions {
+            confirm_threshold: None,
+            ledger_file: Some(String::from(ledger_path.to_str().unwrap())),
+            ..ImportOptions::default()
+        };
+
+        run(&[csv_path.to_str().unwrap()], test_client(&server), &options).unwrap();
+        let ledger = read_ledger(ledger_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&csv_path).unwrap();
+        std::fs::remove_file(&ledger_path).unwrap();
+
+        let posts = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST")
+            .collect::<Vec<Request>>();
+        assert_eq!(posts.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&posts[0].body).unwrap();
+        assert_eq!(body["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(body["transactions"][0]["import_id"], "124");
+        assert_eq!(
+            ledger[ACCOUNT_ID],
+            BTreeSet::from([String::from("123"), String::from("124")])
+        );
+    }
+
+    #[test]
+    fn test_turnover() {
+        let txns = vec![
+            test_transaction("1", -12990),
+            test_fee("1_1", -500),
+            test_transaction("2", 490),
+            test_transaction("3", 1000),
+        ];
+        assert_eq!(turnover(&txns), (1349, 149));
+    }
+
+    #[test]
+    fn test_default_import_options() {
+        let options = ImportOptions::default();
+        assert!(matches!(options.balance_check, BalanceCheck::EndBalance));
+        assert_eq!(options.cleared, "cleared");
+        assert!(options.cleared_map.is_empty());
+        assert_eq!(options.default_payee, DEFAULT_PAYEE);
+        assert!(!options.diff);
+        assert_eq!(options.fee_category, None);
+        assert!(!options.no_rollup);
+        assert!(matches!(options.chunk_order, ChunkOrder::Reverse));
+        assert_eq!(options.max_age, None);
+        assert!(!options.strict);
+        assert_eq!(options.knowledge_file, None);
+        assert!(!options.turnover_check);
+        assert!(options.rollup_suffixes.is_empty());
+        assert_eq!(options.confirm_threshold, Some(100));
+        assert!(options.merge_payment_types.is_empty());
+        assert_eq!(options.merged_payee, "Transfer");
+        assert!(!options.atomic);
+        assert_eq!(options.source_tag, None);
+        assert!(!options.import_interest);
+        assert!(!options.print_unmatched);
+        assert!(!options.create_adjustment);
+        assert_eq!(options.export_path, None);
+        assert_eq!(options.min_amount, None);
+        assert!(options.currency_map.is_empty());
+        assert!(!options.flag_possible_duplicates);
+        assert!(!options.dump_raw);
+        assert_eq!(options.skip_lines, None);
+        assert_eq!(options.ledger_file, None);
+        assert_eq!(options.concurrency, 4);
+        assert!(options.payee_ids.is_empty());
+    }
+
+    #[test]
+    fn test_needs_confirmation() {
+        assert!(needs_confirmation(101, Some(100)));
+        assert!(!needs_confirmation(100, Some(100)));
+        assert!(!needs_confirmation(1000, None));
+    }
+
+    #[test]
+    fn test_parse_cleared_mapping() {
         assert_eq!(
-            ParsedPayeeMemo::from_str(
-                "Paysera LT",
-                "R000 Pasutijums Nr. 14, projekts https://www.kartes.lv pardevejs: Jana seta"
+            parse_cleared_mapping("CTX=uncleared"),
+            Some((String::from("CTX"), String::from("uncleared")))
+        );
+        assert_eq!(parse_cleared_mapping("CTX=pending"), None);
+        assert_eq!(parse_cleared_mapping("=cleared"), None);
+        assert_eq!(parse_cleared_mapping("CTX"), None);
+    }
+
+    #[test]
+    fn test_cleared_map() {
+        let options = ImportOptions {
+            cleared: String::from("reconciled"),
+            cleared_map: HashMap::from([(String::from("CTX"), String::from("uncleared"))]),
+            ..ImportOptions::default()
+        };
+        let card_txn = from_transaction_row(test_row("Foo", "Bar", "CTX"), "account", &options);
+        assert_eq!(card_txn.cleared, "uncleared");
+        let transfer_txn = from_transaction_row(test_row("Foo", "Bar", "MK"), "account", &options);
+        assert_eq!(transfer_txn.cleared, "reconciled");
+    }
+
+    fn merge_options() -> ImportOptions {
+        ImportOptions {
+            merge_payment_types: vec![String::from("MK")],
+            ..ImportOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_merged_payee_memo() {
+        let txn = from_transaction_row(test_row("John Doe", "Invoice 123", "MK"), "account", &merge_options());
+        assert_eq!(txn.payee_name, String::from("Transfer"));
+        assert_eq!(txn.memo, Some(String::from("John Doe: Invoice 123")));
+    }
+
+    #[test]
+    fn test_unmerged_payment_type() {
+        let txn = from_transaction_row(test_row("John Doe", "Invoice 123", "CTX"), "account", &merge_options());
+        assert_eq!(txn.payee_name, String::from("John Doe"));
+        assert_eq!(txn.memo, Some(String::from("Invoice 123")));
+    }
+
+    #[test]
+    fn test_merge_payee_memo_without_payee() {
+        assert_eq!(merge_payee_memo(" ", "Invoice 123"), Some(String::from("Invoice 123")));
+        assert_eq!(merge_payee_memo("", ""), None);
+    }
+
+    fn failing_import_server() -> MockServer {
+        MockServer::with_responses(vec![
+            (
+                201,
+                r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": [], "transaction_ids": ["a", "b"]}}"#,
+            ),
+            (
+                400,
+                r#"{"error": {"id": "400", "name": "bad_request", "detail": "Bad request"}}"#,
+            ),
+            (200, "{}"),
+            (200, "{}"),
+        ])
+    }
+
+    #[test]
+    fn test_atomic_import_rollback() {
+        let server = failing_import_server();
+        let txns = (0..51)
+            .map(|i| test_transaction(&i.to_string(), 100))
+            .collect::<Vec<YnabTransaction>>();
+
+        assert!(post_chunks(&test_client(&server), &txns, ChunkOrder::Forward, true, 1).is_err());
+        let requests = server
+            .requests()
+            .into_iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect::<Vec<String>>();
+        let transactions_path = format!("/v1/budgets/{}/transactions", BUDGET_ID);
+        assert_eq!(
+            requests,
+            vec![
+                format!("POST {}", transactions_path),
+                format!("POST {}", transactions_path),
+                format!("DELETE {}/a", transactions_path),
+                format!("DELETE {}/b", transactions_path),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_import_without_rollback() {
+        let server = failing_import_server();
+        let txns = (0..51)
+            .map(|i| test_transaction(&i.to_string(), 100))
+            .collect::<Vec<YnabTransaction>>();
+
+        assert!(post_chunks(&test_client(&server), &txns, ChunkOrder::Forward, false, 1).is_err());
+        assert!(server.requests().iter().all(|r| r.method == "POST"));
+    }
+
+    #[test]
+    fn test_import_concurrency() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let (server_in_flight, server_max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+        let server = MockServer::start(move |_| {
+            let current = server_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            server_max_in_flight.fetch_max(current, Ordering::SeqCst);
+            thread::sleep(std::time::Duration::from_millis(50));
+            server_in_flight.fetch_sub(1, Ordering::SeqCst);
+            (
+                201,
+                String::from(r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#),
             )
-            .payee,
-            "Jana seta"
+        });
+        let txns = (0..300)
+            .map(|i| test_transaction(&i.to_string(), 100))
+            .collect::<Vec<YnabTransaction>>();
+
+        post_chunks(&test_client(&server), &txns, ChunkOrder::Forward, false, 2).unwrap();
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_normalize_currency() {
+        assert_eq!(normalize_currency("EUR "), String::from("EUR"));
+        assert_eq!(normalize_currency(" eur"), String::from("EUR"));
+        assert_eq!(normalize_currency("EUR"), String::from("EUR"));
+    }
+
+    #[test]
+    fn test_tag_memo() {
+        let fields = MemoFields {
+            memo: Some("Invoice 123"),
+            source: Some("#swedbank-eur"),
+            ..MemoFields::default()
+        };
+        assert_eq!(
+            build_memo(DEFAULT_MEMO_FORMAT, &fields),
+            Some(String::from("Invoice 123 #swedbank-eur"))
+        );
+    }
+
+    #[test]
+    fn test_tag_empty_memo() {
+        let fields = MemoFields {
+            source: Some("#swedbank-eur"),
+            ..MemoFields::default()
+        };
+        assert_eq!(
+            build_memo(DEFAULT_MEMO_FORMAT, &fields),
+            Some(String::from("#swedbank-eur"))
+        );
+    }
+
+    #[test]
+    fn test_tag_long_memo() {
+        let memo = "a".repeat(MAX_MEMO_LENGTH);
+        let fields = MemoFields {
+            memo: Some(&memo),
+            source: Some("#swedbank-eur"),
+            ..MemoFields::default()
+        };
+        let tagged = build_memo(DEFAULT_MEMO_FORMAT, &fields).unwrap();
+        assert_eq!(tagged.chars().count(), MAX_MEMO_LENGTH);
+        assert!(tagged.ends_with(" #swedbank-eur"));
+    }
+
+    #[test]
+    fn test_default_memo_format() {
+        let row = test_row("Rimi", "PIRKUMS 0******1 30.07.2024 13:07 5.00 EUR (123456) Rimi", "");
+        let txn = from_transaction_row(row, "account", &ImportOptions::default());
+        assert_eq!(txn.memo, Some(String::from("(123456) Rimi")));
+        assert_eq!(build_memo(DEFAULT_MEMO_FORMAT, &MemoFields::default()), None);
+    }
+
+    fn test_fx_row() -> SwedbankCsv {
+        SwedbankCsv {
+            transaction_id: String::from("2024073000123456"),
+            ..test_row(
+                "Abc",
+                "PIRKUMS 0******1 30.07.24 13:07 24.90 CHF, ATTIECĪBĀ PRET ECB VALŪTAS KURSU 2.3% (123456) Abc",
+                "CTX",
+            )
+        }
+    }
+
+    #[test]
+    fn test_fx_details() {
+        assert_eq!(fx_details(&test_fx_row().memo), Some(String::from("24.90 CHF")));
+        assert_eq!(
+            fx_details("PIRKUMS 0******1 30.07.2024 13:07 5.00 EUR (123456) Rimi"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_memo_format() {
+        let options = ImportOptions {
+            memo_format: String::from("{reference} | {merchant} ({fx}) [{type}]"),
+            ..ImportOptions::default()
+        };
+        let txn = from_transaction_row(test_fx_row(), "account", &options);
+        assert_eq!(txn.memo, Some(String::from("2024073000123456 | Abc (24.90 CHF) [CTX]")));
+    }
+
+    #[test]
+    fn test_memo_format_missing_placeholders() {
+        let options = ImportOptions {
+            memo_format: String::from("{fx} / {reference} / {memo} / {source}"),
+            source_tag: Some(String::from("#swedbank-eur")),
+            ..ImportOptions::default()
+        };
+        let row = SwedbankCsv {
+            transaction_id: String::from("2024073000123456"),
+            ..test_row("", "Rent", "")
+        };
+        let txn = from_transaction_row(row, "account", &options);
+        assert_eq!(txn.memo, Some(String::from("2024073000123456 / Rent / #swedbank-eur")));
+
+        let fields = MemoFields {
+            merchant: "Abc",
+            ..MemoFields::default()
+        };
+        assert_eq!(
+            build_memo("Ref: {reference}, {merchant}.", &fields),
+            Some(String::from("Ref: Abc."))
+        );
+        assert_eq!(build_memo("Ref: {reference}", &fields), None);
+    }
+
+    #[test]
+    fn test_literal_memo_format() {
+        assert_eq!(
+            build_memo("Imported", &MemoFields::default()),
+            Some(String::from("Imported"))
+        );
+        assert!(validate_memo_format("Imported").is_ok());
+    }
+
+    #[test]
+    fn test_validate_memo_format() {
+        assert!(validate_memo_format(DEFAULT_MEMO_FORMAT).is_ok());
+        assert!(validate_memo_format("{ reference } | {fx}").is_ok());
+        assert!(validate_memo_format("Ref {reference").is_err());
+        assert!(validate_memo_format("Ref reference}").is_err());
+        assert!(validate_memo_format("{{memo}}").is_err());
+        assert!(validate_memo_format("{memo} {unknown}").is_err());
+    }
+
+    fn test_interest_row(debit_or_credit: EntryType) -> SwedbankCsv {
+        SwedbankCsv {
+            record_type: RecordType::Interest,
+            memo: String::from("Procenti"),
+            amount: String::from("1,25"),
+            debit_or_credit: Some(debit_or_credit),
+            transaction_id: String::new(),
+            ..test_row("", "", "")
+        }
+    }
+
+    #[test]
+    fn test_earned_interest() {
+        let txn = from_interest_row(
+            test_interest_row(EntryType::Credit),
+            "account",
+            &ImportOptions::default(),
+        );
+        assert_eq!(txn.amount, 1250);
+        assert_eq!(txn.payee_name, String::from("Swedbank interest"));
+        assert_eq!(txn.memo, Some(String::from("Procenti")));
+        assert_eq!(txn.import_id, String::from("INT:2020-02-09:1250"));
+    }
+
+    #[test]
+    fn test_charged_interest() {
+        let txn = from_interest_row(
+            test_interest_row(EntryType::Debit),
+            "account",
+            &ImportOptions::default(),
+        );
+        assert_eq!(txn.amount, -1250);
+        assert_eq!(txn.payee_name, String::from("Swedbank interest"));
+        assert_eq!(txn.import_id, String::from("INT:2020-02-09:-1250"));
+    }
+
+    #[test]
+    fn test_interest_cleared_map() {
+        let options = ImportOptions {
+            cleared_map: HashMap::from([(String::from("INT"), String::from("reconciled"))]),
+            ..ImportOptions::default()
+        };
+        let row = SwedbankCsv {
+            payment_type: String::from("INT"),
+            ..test_interest_row(EntryType::Credit)
+        };
+        assert_eq!(from_interest_row(row, "account", &options).cleared, "reconciled");
+        let txn = from_interest_row(test_interest_row(EntryType::Credit), "account", &options);
+        assert_eq!(txn.cleared, "cleared");
+    }
+
+    #[test]
+    fn test_unmatched_payees() {
+        let unmatched = |import_id, payee: &str| YnabTransaction {
+            raw_payee: String::from(payee),
+            payee_matched: false,
+            ..test_transaction(import_id, 100)
+        };
+        let txns = vec![
+            unmatched("1", "Foo"),
+            unmatched("2", "Bar"),
+            test_transaction("3", 100),
+            unmatched("4", "Bar"),
+        ];
+        assert_eq!(unmatched_payees(&txns), vec![("Bar", 2), ("Foo", 1)]);
+    }
+
+    static CSV_HEADER: &str = "\"Ieraksta tips\";\"Datums\";\"Saņēmējs/Maksātājs\";\"Informācija saņēmējam\";\"Summa\";\"Valūta\";\"Debets/Kredīts\";\"Arhīva kods\";\"Maksājuma veids\"";
+
+    fn test_statement(rows: &[&str]) -> Statement {
+        let csv = format!("{}\n{}\n", CSV_HEADER, rows.join("\n"));
+        let accounts = [(String::from("EUR"), String::from(ACCOUNT_ID))];
+        let (mut statements, _) = read_statements(csv.as_bytes(), &accounts, &ImportOptions::default()).unwrap();
+        statements.remove(0)
+    }
+
+    #[test]
+    fn test_read_statement() {
+        let statement = test_statement(&[
+            "\"10\";\"01.02.2020\";\"\";\"Sākuma atlikums\";\"100,00\";\"EUR\";\"K\";\"\";\"AS\"",
+            "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"",
+            "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"USD\";\"D\";\"124\";\"CTX\"",
+            "\"86\";\"29.02.2020\";\"\";\"Beigu atlikums\";\"95,00\";\"EUR\";\"K\";\"\";\"LS\"",
+        ]);
+        assert_eq!(statement.txns.len(), 1);
+        assert_eq!(statement.txns[0].amount, -5000);
+        assert_eq!(statement.start_balance, 10000);
+        assert_eq!(statement.end_balance, 9500);
+        assert_eq!(statement.end_date, Some(String::from("2020-02-29")));
+    }
+
+    static OTHER_ACCOUNT_ID: &str = "fedcba98-7654-3210-fedc-ba9876543211";
+
+    static MULTI_CURRENCY_CSV: &[&str] = &[
+        "\"10\";\"01.02.2020\";\"\";\"Sākuma atlikums\";\"100,00\";\"EUR\";\"K\";\"\";\"AS\"",
+        "\"10\";\"01.02.2020\";\"\";\"Sākuma atlikums\";\"50,00\";\"USD\";\"K\";\"\";\"AS\"",
+        "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"",
+        "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"10,00\";\"USD\";\"D\";\"124\";\"CTX\"",
+        "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"1,00\";\"GBP\";\"D\";\"125\";\"CTX\"",
+        "\"86\";\"29.02.2020\";\"\";\"Beigu atlikums\";\"95,00\";\"EUR\";\"K\";\"\";\"LS\"",
+        "\"86\";\"29.02.2020\";\"\";\"Beigu atlikums\";\"40,00\";\"USD\";\"K\";\"\";\"LS\"",
+    ];
+
+    fn test_currency_map() -> Vec<(String, String)> {
+        vec![
+            (String::from("EUR"), String::from(ACCOUNT_ID)),
+            (String::from("USD"), String::from(OTHER_ACCOUNT_ID)),
+        ]
+    }
+
+    #[test]
+    fn test_parse_currency_map() {
+        let map = parse_currency_map(&format!(
+            "# Swedbank accounts\neur = {}\n\nUSD={}\n",
+            ACCOUNT_ID, OTHER_ACCOUNT_ID
+        ))
+        .unwrap();
+        assert_eq!(map, test_currency_map());
+        assert!(parse_currency_map("EUR").is_err());
+    }
+
+    #[test]
+    fn test_parse_payee_map() {
+        let map = parse_payee_map(&format!(
+            "# Payees
+Rimi = {}
+
+",
+            OTHER_ACCOUNT_ID
+        ))
+        .unwrap();
+        assert_eq!(map.get("Rimi"), Some(&String::from(OTHER_ACCOUNT_ID)));
+        assert!(parse_payee_map("Rimi").is_err());
+        assert!(parse_payee_map("Rimi = abc").is_err());
+    }
+
+    #[test]
+    fn test_mapped_payee_id() {
+        let payee_ids = parse_payee_map(&format!("Foo = {}", OTHER_ACCOUNT_ID)).unwrap();
+        let mut txns = vec![
+            test_transaction("1", 100),
+            YnabTransaction {
+                payee_name: String::from("Bar"),
+                ..test_transaction("2", 100)
+            },
+        ];
+        apply_payee_ids(&mut txns, &payee_ids);
+
+        let json = serde_json::to_value(&txns).unwrap();
+        assert_eq!(json[0]["payee_id"], OTHER_ACCOUNT_ID);
+        assert!(json[0].get("payee_name").is_none());
+        assert_eq!(json[1]["payee_name"], "Bar");
+        assert!(json[1].get("payee_id").is_none());
+    }
+
+    #[test]
+    fn test_read_multi_currency_statements() {
+        let csv = format!("{}\n{}\n", CSV_HEADER, MULTI_CURRENCY_CSV.join("\n"));
+        let (statements, skipped) =
+            read_statements(csv.as_bytes(), &test_currency_map(), &ImportOptions::default()).unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(statements[0].txns.len(), 1);
+        assert_eq!(statements[0].txns[0].account_id, ACCOUNT_ID);
+        assert_eq!(statements[0].txns[0].amount, -5000);
+        assert_eq!(statements[0].end_balance, 9500);
+        assert_eq!(statements[1].txns.len(), 1);
+        assert_eq!(statements[1].txns[0].account_id, OTHER_ACCOUNT_ID);
+        assert_eq!(statements[1].txns[0].amount, -10000);
+        assert_eq!(statements[1].end_balance, 4000);
+    }
+
+    #[test]
+    fn test_multi_currency_atomic_rollback() {
+        // EUR transactions are imported, posting USD transactions fails
+        let server = MockServer::start(|request| match request.method.as_str() {
+            "POST" if request.body.contains(OTHER_ACCOUNT_ID) => (400, String::from("{}")),
+            "POST" => (
+                201,
+                String::from(
+                    r#"{"data": {"server_knowledge": 1, "transaction_ids": ["a"], "duplicate_import_ids": [], "transactions": []}}"#,
+                ),
+            ),
+            _ => (200, String::from("{}")),
+        });
+        let path = std::env::temp_dir().join("ynab-swed-test-multi-currency-atomic.csv");
+        std::fs::write(&path, format!("{}\n{}\n", CSV_HEADER, MULTI_CURRENCY_CSV.join("\n"))).unwrap();
+        let options = ImportOptions {
+            currency_map: test_currency_map(),
+            confirm_threshold: None,
+            atomic: true,
+            ..ImportOptions::default()
+        };
+
+        assert!(run(&[path.to_str().unwrap()], test_client(&server), &options).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let requests = server
+            .requests()
+            .into_iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect::<Vec<String>>();
+        let transactions_path = format!("/v1/budgets/{}/transactions", BUDGET_ID);
+        assert_eq!(
+            requests,
+            vec![
+                format!("POST {}", transactions_path),
+                format!("POST {}", transactions_path),
+                format!("DELETE {}/a", transactions_path),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_currency_partial_failure_ledger() {
+        // EUR transactions are imported, posting USD transactions fails
+        let server = MockServer::start(|request| match request.method.as_str() {
+            "POST" if request.body.contains(OTHER_ACCOUNT_ID) => (400, String::from("{}")),
+            "POST" => (
+                201,
+                String::from(r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#),
+            ),
+            _ => (200, String::from("{}")),
+        });
+        let path = std::env::temp_dir().join("ynab-swed-test-multi-currency-partial.csv");
+        let ledger_path = std::env::temp_dir().join("ynab-swed-test-multi-currency-partial.json");
+        std::fs::write(&path, format!("{}\n{}\n", CSV_HEADER, MULTI_CURRENCY_CSV.join("\n"))).unwrap();
+        let options = ImportOptions {
+            currency_map: test_currency_map(),
+            confirm_threshold: None,
+            ledger_file: Some(String::from(ledger_path.to_str().unwrap())),
+            ..ImportOptions::default()
+        };
+
+        assert!(run(&[path.to_str().unwrap()], test_client(&server), &options).is_err());
+        let ledger = read_ledger(ledger_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&ledger_path).unwrap();
+
+        assert!(ledger[ACCOUNT_ID].contains("123"));
+        assert!(!ledger.contains_key(OTHER_ACCOUNT_ID));
+    }
+
+    #[test]
+    fn test_multi_currency_balance_check() {
+        // EUR account balance matches the statement, USD account balance is 10.00 too high
+        let server = MockServer::start(|request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", _) => (
+                201,
+                String::from(r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#),
+            ),
+            ("GET", p) if p.ends_with(ACCOUNT_ID) => {
+                (200, String::from(r#"{"data": {"account": {"balance": 95000}}}"#))
+            }
+            ("GET", p) if p.ends_with(OTHER_ACCOUNT_ID) => {
+                (200, String::from(r#"{"data": {"account": {"balance": 50000}}}"#))
+            }
+            _ => (404, String::from("{}")),
+        });
+        let path = std::env::temp_dir().join("ynab-swed-test-multi-currency.csv");
+        std::fs::write(&path, format!("{}\n{}\n", CSV_HEADER, MULTI_CURRENCY_CSV.join("\n"))).unwrap();
+        let options = ImportOptions {
+            currency_map: test_currency_map(),
+            confirm_threshold: None,
+            create_adjustment: true,
+            ..ImportOptions::default()
+        };
+
+        run(&[path.to_str().unwrap()], test_client(&server), &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let posted = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST")
+            .map(|r| serde_json::from_str::<serde_json::Value>(&r.body).unwrap()["transactions"].clone())
+            .collect::<Vec<serde_json::Value>>();
+        assert_eq!(posted.len(), 3);
+        assert_eq!(posted[0][0]["account_id"], ACCOUNT_ID);
+        assert_eq!(posted[1][0]["account_id"], OTHER_ACCOUNT_ID);
+        assert_eq!(posted[2][0]["account_id"], OTHER_ACCOUNT_ID);
+        assert_eq!(posted[2][0]["payee_name"], "Balance Adjustment");
+        assert_eq!(posted[2][0]["amount"], -10000);
+    }
+
+    #[test]
+    fn test_flag_possible_duplicates() {
+        let mut txns = vec![
+            test_transaction("1", -2500),
+            test_transaction("2", -2500),
+            test_transaction("3", -3000),
+            YnabTransaction {
+                date: String::from("2020-02-10"),
+                ..test_transaction("4", -2500)
+            },
+        ];
+        assert_eq!(flag_possible_duplicates(&mut txns), 2);
+        assert_eq!(txns.len(), 4);
+        assert_eq!(txns[0].flag_color, Some(String::from("yellow")));
+        assert_eq!(txns[1].flag_color, Some(String::from("yellow")));
+        assert_eq!(txns[2].flag_color, None);
+        assert_eq!(txns[3].flag_color, None);
+    }
+
+    static PREAMBLE_CSV: &str = "\"Konts LV00HABA0000000000000\";\"15.03.2020\"\n\"Ieraksta tips\";\"Datums\";\"Saņēmējs/Maksātājs\";\"Informācija saņēmējam\";\"Summa\";\"Valūta\";\"Debets/Kredīts\";\"Arhīva kods\";\"Maksājuma veids\"\n\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"\n";
+
+    #[test]
+    fn test_csv_preamble() {
+        let rows = read_rows(PREAMBLE_CSV.as_bytes(), None).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].payee, "Foo");
+        assert_eq!(rows[0].transaction_id, "123");
+    }
+
+    #[test]
+    fn test_csv_skip_lines() {
+        assert_eq!(read_rows(PREAMBLE_CSV.as_bytes(), Some(1)).unwrap().len(), 1);
+        assert!(read_rows(PREAMBLE_CSV.as_bytes(), Some(0)).is_err());
+        assert!(read_rows(PREAMBLE_CSV.as_bytes(), Some(2)).is_err());
+        assert!(read_rows(PREAMBLE_CSV.as_bytes(), Some(3)).is_err());
+        assert!(read_rows(PREAMBLE_CSV.as_bytes(), Some(10)).is_err());
+    }
+
+    static SEPARATE_AMOUNTS_HEADER: &str = "\"Ieraksta tips\";\"Datums\";\"Saņēmējs/Maksātājs\";\"Informācija saņēmējam\";\"Debets\";\"Kredīts\";\"Valūta\";\"Arhīva kods\";\"Maksājuma veids\"";
+
+    #[test]
+    fn test_revolut_like_payee() {
+        let txn = from_transaction_row(
+            test_row("Revolution Bar", "Beer", "CTX"),
+            "account",
+            &ImportOptions::default(),
+        );
+        assert_eq!(txn.payee_name, "Revolution Bar");
+    }
+
+    #[test]
+    fn test_separate_amount_columns() {
+        let csv = format!(
+            "{}\n{}\n{}\n",
+            SEPARATE_AMOUNTS_HEADER,
+            "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"12,99\";\"\";\"EUR\";\"123\";\"CTX\"",
+            "\"20\";\"10.02.2020\";\"Foo\";\"Bar\";\"\";\"0,49\";\"EUR\";\"124\";\"MK\""
+        );
+        let rows = read_rows(csv.as_bytes(), None).unwrap();
+        assert_eq!(row_amount(&rows[0]), Some(-12990));
+        assert_eq!(row_amount(&rows[1]), Some(490));
+    }
+
+    #[test]
+    fn test_missing_debit_or_credit() {
+        let csv = format!(
+            "{}\n{}\n",
+            CSV_HEADER, "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"\";\"123\";\"CTX\""
+        );
+        let err = read_rows(csv.as_bytes(), None).unwrap_err().to_string();
+        assert!(err.contains("no amount or no debit/credit indicator"));
+    }
+
+    #[test]
+    fn test_separate_amount_columns_statement() {
+        let csv = [
+            SEPARATE_AMOUNTS_HEADER,
+            "\"10\";\"01.02.2020\";\"\";\"Sākuma atlikums\";\"\";\"100,00\";\"EUR\";\"\";\"AS\"",
+            "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"125,00\";\"\";\"EUR\";\"123\";\"CTX\"",
+            "\"82\";\"29.02.2020\";\"\";\"Apgrozījums\";\"125,00\";\"0,00\";\"EUR\";\"\";\"AS\"",
+            "\"86\";\"29.02.2020\";\"\";\"Beigu atlikums\";\"25,00\";\"\";\"EUR\";\"\";\"LS\"",
+        ]
+        .join("\n");
+        let accounts = [(String::from("EUR"), String::from(ACCOUNT_ID))];
+        let (statements, _) = read_statements(csv.as_bytes(), &accounts, &ImportOptions::default()).unwrap();
+        assert_eq!(statements[0].start_balance, 10000);
+        assert_eq!(statements[0].end_balance, -2500);
+        assert_eq!(statements[0].debit_turnover, Some(12500));
+        assert_eq!(statements[0].credit_turnover, Some(0));
+        assert_eq!(statements[0].txns[0].amount, -125000);
+    }
+
+    #[test]
+    fn test_dedup_transactions() {
+        let mut txns = vec![
+            test_transaction("1", 100),
+            test_transaction("2", 100),
+            test_transaction("1", 100),
+        ];
+        assert_eq!(dedup_transactions(&mut txns), 1);
+        assert_eq!(
+            txns.iter().map(|t| t.import_id.as_str()).collect::<Vec<&str>>(),
+            vec!["1", "2"]
+        );
+    }
+
+    #[test]
+    fn test_overlapping_statements_import() {
+        let server = MockServer::start(|request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", _) => (
+                201,
+                String::from(r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#),
+            ),
+            ("GET", p) if p.ends_with(BUDGET_ID) => (
+                200,
+                String::from(r#"{"data": {"budget": {"currency_format": {"iso_code": "EUR"}}}}"#),
+            ),
+            _ => (200, String::from(r#"{"data": {"account": {"balance": 0}}}"#)),
+        });
+        let first_path = std::env::temp_dir().join("ynab-swed-test-overlap-1.csv");
+        let second_path = std::env::temp_dir().join("ynab-swed-test-overlap-2.csv");
+        std::fs::write(
+            &first_path,
+            format!(
+                "{}\n{}\n{}\n",
+                CSV_HEADER,
+                "\"20\";\"09.02.2020\";\"Foo\";\"Bar\";\"5,00\";\"EUR\";\"D\";\"123\";\"CTX\"",
+                "\"20\";\"10.02.2020\";\"Foo\";\"Bar\";\"6,00\";\"EUR\";\"D\";\"124\";\"CTX\""
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            &second_path,
+            format!(
+                "{}\n{}\n{}\n",
+                CSV_HEADER,
+                "\"20\";\"10.02.2020\";\"Foo\";\"Bar\";\"6,00\";\"EUR\";\"D\";\"124\";\"CTX\"",
+                "\"20\";\"11.02.2020\";\"Foo\";\"Bar\";\"7,00\";\"EUR\";\"D\";\"125\";\"CTX\""
+            ),
+        )
+        .unwrap();
+        let options = ImportOptions {
+            confirm_threshold: None,
+            chunk_order: ChunkOrder::Forward,
+            ..ImportOptions::default()
+        };
+
+        run(
+            &[first_path.to_str().unwrap(), second_path.to_str().unwrap()],
+            test_client(&server),
+            &options,
+        )
+        .unwrap();
+        std::fs::remove_file(first_path).unwrap();
+        std::fs::remove_file(second_path).unwrap();
+
+        let posts = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST")
+            .collect::<Vec<Request>>();
+        assert_eq!(posts.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&posts[0].body).unwrap();
+        let import_ids = body["transactions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["import_id"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(import_ids, vec!["123", "124", "125"]);
+    }
+
+    #[test]
+    fn test_export_import_json() {
+        let server = MockServer::with_responses(vec![(
+            201,
+            r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#,
+        )]);
+        let mut txns = vec![test_transaction("1", -1000), test_transaction("2", 2000)];
+        txns[1].category_id = Some(String::from("category"));
+        let path = std::env::temp_dir().join("ynab-swed-test-export.json");
+        let path = path.to_str().unwrap();
+
+        export_transactions(path, &txns).unwrap();
+        import_json(path, test_client(&server), &ImportOptions::default()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(body["transactions"], serde_json::to_value(&txns).unwrap());
+    }
+
+    #[test]
+    fn test_check() {
+        let server = MockServer::with_responses(vec![
+            (200, r#"{"data": {"user": {"id": "user"}}}"#),
+            (200, r#"{"data": {"budget": {"currency_format": {"iso_code": "EUR"}}}}"#),
+            (200, r#"{"data": {"account": {"balance": 12340}}}"#),
+        ]);
+        check(&test_client(&server)).unwrap();
+
+        let requests = server.requests();
+        assert!(requests.iter().all(|r| r.method == "GET"));
+        let paths = requests.iter().map(|r| r.path.as_str()).collect::<Vec<&str>>();
+        assert_eq!(
+            paths,
+            vec![
+                String::from("/v1/user"),
+                format!("/v1/budgets/{}", BUDGET_ID),
+                format!("/v1/budgets/{}/accounts/{}", BUDGET_ID, ACCOUNT_ID),
+            ]
         );
     }
 
+    #[test]
+    fn test_check_invalid_token() {
+        let server = MockServer::with_responses(vec![(
+            401,
+            r#"{"error": {"id": "401", "name": "unauthorized", "detail": "Unauthorized"}}"#,
+        )]);
+        let err = check(&test_client(&server)).unwrap_err().to_string();
+        assert!(err.starts_with("Access token: FAILED, the token is invalid or has expired"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_check_connection_error() {
+        // Nothing is listening on the port once the listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token")
+            .unwrap()
+            .with_api_url(&url);
+        let err = check(&client).unwrap_err().to_string();
+        assert!(err.starts_with("Connection to YNAB: FAILED"));
+    }
+
     #[test]
     fn test_tx_date() {
         assert_eq!(fmt_date("09.02.2020"), String::from("2020-02-09"));
+        assert_eq!(fmt_date("30.07.24"), String::from("2024-07-30"));
+    }
+
+    #[test]
+    fn test_unpadded_tx_date() {
+        assert_eq!(fmt_date("9.2.2020"), String::from("2020-02-09"));
+        assert_eq!(fmt_date("19.2.2020"), String::from("2020-02-19"));
     }
 
     #[test]
@@ -374,6 +3520,16 @@ mod tests {
         assert_eq!(fmt_amount("0,49", &EntryType::Credit), 490);
     }
 
+    #[test]
+    fn test_signed_debit_amount() {
+        assert_eq!(fmt_amount("12,99-", &EntryType::Debit), -12990);
+    }
+
+    #[test]
+    fn test_currency_credit_amount() {
+        assert_eq!(fmt_amount("12,99 EUR", &EntryType::Credit), 12990);
+    }
+
     #[test]
     fn test_commission_txid() {
         assert_eq!(fmt_transaction_id("123", "KOM", "Foo"), String::from("123_1"));
@@ -396,27 +3552,60 @@ mod tests {
 
     #[test]
     fn test_local_tx_fee_memo() {
-        assert_eq!(needs_rollup("Maksājumu uzdevuma apkalpošanas komisija", "KOM"), true);
+        assert!(needs_rollup("Maksājumu uzdevuma apkalpošanas komisija", "KOM", &[]));
     }
 
     #[test]
     fn test_local_tx_no_fee_memo() {
-        assert_eq!(needs_rollup("Maksājumu uzdevuma apkalpošanas komisija", "CTX"), false);
+        assert!(!needs_rollup("Maksājumu uzdevuma apkalpošanas komisija", "CTX", &[]));
     }
 
     #[test]
     fn test_international_tx_fee_memo() {
-        assert_eq!(
-            needs_rollup("Ārvalstu Maksājumu uzdevumu apkalpošanas komisija", "KOM"),
-            true
-        );
+        assert!(needs_rollup(
+            "Ārvalstu Maksājumu uzdevumu apkalpošanas komisija",
+            "KOM",
+            &[]
+        ));
     }
 
     #[test]
     fn test_other_kom_tx_memo() {
-        assert_eq!(
-            needs_rollup("Kartes mēneša maksa 000000******0000 02.2020", "KOM"),
-            false
-        );
+        assert!(!needs_rollup(
+            "Kartes mēneša maksa 000000******0000 02.2020",
+            "KOM",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_card_fee() {
+        let mut txns = vec![
+            from_transaction_row(test_row("Abc", "Abc", "CTX"), "account", &ImportOptions::default()),
+            from_transaction_row(
+                test_row("", "Kartes mēneša maksa 000000******0000 02.2020", "KOM"),
+                "account",
+                &ImportOptions::default(),
+            ),
+        ];
+        assert_eq!(txns[1].payee_name, "Swedbank card fee");
+        assert_eq!(txns[1].memo, Some(String::from("000000******0000 02.2020")));
+        assert_eq!(rollup_commissions(&mut txns), (0, 0));
+        assert_eq!(txns.len(), 2);
+    }
+
+    #[test]
+    fn test_custom_fee_memo() {
+        let options = ImportOptions {
+            rollup_suffixes: vec![String::from(" darījuma komisija")],
+            ..ImportOptions::default()
+        };
+        let mut txns = vec![
+            from_transaction_row(test_row("Abc", "Abc", "CTX"), "account", &options),
+            from_transaction_row(test_row("", "Kartes darījuma komisija", "KOM"), "account", &options),
+        ];
+        rollup_commissions(&mut txns);
+        assert_eq!(txns.len(), 1);
+        assert_eq!(txns[0].amount, -10000);
     }
 }
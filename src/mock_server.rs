@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// HTTP request received by the mock server
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// Returns the value of the given header, header names are matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+type Handler = dyn Fn(&Request) -> (u16, String) + Send + Sync;
+
+/// Minimal HTTP server for testing YnabClient requests.
+/// Each connection is handled in its own thread and is closed after a single response.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    headers: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl MockServer {
+    /// Starts a server that responds to requests with the given handler
+    pub fn start(handler: impl Fn(&Request) -> (u16, String) + Send + Sync + 'static) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let server_requests = requests.clone();
+        let server_headers = headers.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let requests = server_requests.clone();
+                let headers = server_headers.clone();
+                let handler = handler.clone();
+                thread::spawn(move || handle_connection(stream, &requests, &headers, handler.as_ref()));
+            }
+        });
+
+        MockServer { url, requests, headers }
+    }
+
+    /// Adds a header to all the following responses
+    pub fn add_header(&self, name: &str, value: &str) {
+        self.headers
+            .lock()
+            .unwrap()
+            .push((String::from(name), String::from(value)));
+    }
+
+    /// Starts a server that responds with the given status codes and bodies, in order.
+    /// Requests that arrive after all the responses are used get a 404.
+    pub fn with_responses(responses: Vec<(u16, &str)>) -> MockServer {
+        let responses = Mutex::new(
+            responses
+                .into_iter()
+                .map(|(status, body)| (status, String::from(body)))
+                .collect::<VecDeque<(u16, String)>>(),
+        );
+        MockServer::start(move |_| {
+            responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or((404, String::from("{}")))
+        })
+    }
+
+    /// Returns all the requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = String::from(parts.next()?);
+    let path = String::from(parts.next()?);
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        match line.trim_end().split_once(": ") {
+            Some((name, value)) => headers.push((String::from(name), String::from(value))),
+            None => break,
+        }
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+    let content_length = request
+        .header("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    request.body = String::from_utf8(body).ok()?;
+    Some(request)
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    requests: &Mutex<Vec<Request>>,
+    headers: &Mutex<Vec<(String, String)>>,
+    handler: &Handler,
+) {
+    if let Some(request) = read_request(&stream) {
+        requests.lock().unwrap().push(request.clone());
+        let (status, body) = handler(&request);
+        let headers = headers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            headers,
+            body
+        );
+    }
+}
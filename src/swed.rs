@@ -33,12 +33,18 @@ pub struct SwedbankCsv {
     pub payee: String,
     #[serde(alias = "Informācija saņēmējam", alias = "Selgitus")]
     pub memo: String,
-    #[serde(alias = "Summa")]
+    // Empty in exports with separate debit and credit amount columns
+    #[serde(alias = "Summa", default)]
     pub amount: String,
     #[serde(alias = "Valūta", alias = "Valuuta")]
     pub currency: String,
-    #[serde(alias = "Debets/Kredīts", alias = "Deebet/Kreedit")]
-    pub debit_or_credit: EntryType,
+    #[serde(alias = "Debets/Kredīts", alias = "Deebet/Kreedit", default)]
+    pub debit_or_credit: Option<EntryType>,
+    // Amount columns of exports that have separate columns for debit and credit amounts
+    #[serde(alias = "Debets", alias = "Deebet", default)]
+    pub debit_amount: Option<String>,
+    #[serde(alias = "Kredīts", alias = "Kreedit", default)]
+    pub credit_amount: Option<String>,
     #[serde(alias = "Arhīva kods", alias = "Arhiveerimistunnus")]
     pub transaction_id: String,
     #[serde(alias = "Maksājuma veids", alias = "Tehingu tüüp")]
@@ -62,12 +68,163 @@ pub fn is_foreign_currency_tx(memo: &str) -> bool {
 
 // Known merchants of record
 
+/// Extracts the actual seller (and remaining memo) from transactions that
+/// went through a merchant of record.
+pub trait MerchantParser {
+    fn matches(&self, payee: &str) -> bool;
+    fn parse(&self, payee: &str, memo: &str) -> (String, Option<String>);
+}
+
+pub struct Amazon;
+pub struct MakeCommerce;
+pub struct PayPal;
+pub struct Paysera;
+pub struct Revolut;
+pub struct Trustly;
+pub struct Zettle;
+
+// Payee prefixes of Amazon sub-brands and the payees they are imported as.
+// Other payees starting with "AMZN" are imported as "Amazon".
+const AMAZON_PAYEES: &[(&str, &str)] = &[
+    ("AMZN Mktp", "Amazon Marketplace"),
+    ("AMZN Digital", "Amazon Digital"),
+    ("Amazon Prime", "Amazon Prime"),
+    ("Kindle Svcs", "Kindle Svcs"),
+];
+
+// Payees of Revolut top-ups and transfers, card purchases have "Revolut**" payees instead
+const REVOLUT_PAYEES: &[&str] = &["Revolut Ltd", "Revolut Bank UAB", "Revolut Payments UAB"];
+
+// Payee prefixes used by Zettle (formerly iZettle), in lowercase
+const ZETTLE_PREFIXES: &[&str] = &["iz *", "izettle *", "zettle_*", "zettle *"];
+
+lazy_static! {
+    // Merchant parsers that are checked, in order, before any other payee formatting is applied.
+    pub static ref MERCHANT_PARSERS: Vec<Box<dyn MerchantParser + Sync>> = {
+        vec![
+            Box::new(MakeCommerce),
+            Box::new(Trustly),
+            Box::new(Paysera),
+            Box::new(Amazon),
+            Box::new(PayPal),
+            Box::new(Revolut),
+            Box::new(Zettle),
+        ]
+    };
+}
+
+/// Returns the memo, unless it only repeats the payee. Surrounding whitespace is ignored in the comparison.
+pub fn memo_unless_payee(payee: &str, memo: &str) -> Option<String> {
+    if memo.trim().starts_with(payee.trim()) {
+        None
+    } else {
+        Some(String::from(memo))
+    }
+}
+
+impl MerchantParser for Amazon {
+    fn matches(&self, payee: &str) -> bool {
+        payee.starts_with("AMZN") || AMAZON_PAYEES.iter().any(|(p, _)| payee.starts_with(p))
+    }
+
+    fn parse(&self, payee: &str, memo: &str) -> (String, Option<String>) {
+        (parse_amazon_payee(payee), Some(parse_amazon_memo(memo)))
+    }
+}
+
+impl MerchantParser for MakeCommerce {
+    fn matches(&self, payee: &str) -> bool {
+        payee == "MakeCommerce"
+    }
+
+    fn parse(&self, _payee: &str, memo: &str) -> (String, Option<String>) {
+        parse_makecommerce_memo(memo)
+    }
+}
+
+impl MerchantParser for PayPal {
+    fn matches(&self, payee: &str) -> bool {
+        payee.starts_with("PAYPAL *")
+    }
+
+    fn parse(&self, payee: &str, memo: &str) -> (String, Option<String>) {
+        (parse_paypal_payee(payee), memo_unless_payee(payee, memo))
+    }
+}
+
+impl MerchantParser for Paysera {
+    fn matches(&self, payee: &str) -> bool {
+        payee == "Paysera LT"
+    }
+
+    fn parse(&self, _payee: &str, memo: &str) -> (String, Option<String>) {
+        parse_paysera_memo(memo)
+    }
+}
+
+impl MerchantParser for Zettle {
+    fn matches(&self, payee: &str) -> bool {
+        let payee = payee.to_lowercase();
+        ZETTLE_PREFIXES.iter().any(|p| payee.starts_with(p))
+    }
+
+    fn parse(&self, payee: &str, memo: &str) -> (String, Option<String>) {
+        (parse_zettle_payee(payee), memo_unless_payee(payee, memo))
+    }
+}
+
+impl MerchantParser for Revolut {
+    fn matches(&self, payee: &str) -> bool {
+        payee.starts_with("Revolut**") || REVOLUT_PAYEES.iter().any(|p| payee.eq_ignore_ascii_case(p))
+    }
+
+    fn parse(&self, payee: &str, memo: &str) -> (String, Option<String>) {
+        if payee.starts_with("Revolut**") {
+            (String::from("Revolut"), memo_unless_payee(payee, memo))
+        } else {
+            parse_revolut_memo(memo)
+        }
+    }
+}
+
+impl MerchantParser for Trustly {
+    fn matches(&self, payee: &str) -> bool {
+        payee == "Trustly Group AB"
+    }
+
+    fn parse(&self, _payee: &str, memo: &str) -> (String, Option<String>) {
+        parse_trustly_memo(memo)
+    }
+}
+
+/// Returns the payee of an Amazon sub-brand, or "Amazon" for unrecognized ones
+pub fn parse_amazon_payee(payee: &str) -> String {
+    AMAZON_PAYEES
+        .iter()
+        .find(|(p, _)| payee.starts_with(p))
+        .map_or("Amazon", |(_, name)| name)
+        .to_string()
+}
+
+/// Returns the order/seller details that follow the asterisk in Amazon memos
+pub fn parse_amazon_memo(memo: &str) -> String {
+    memo.split_once('*')
+        .map(|s| s.1.trim())
+        .filter(|s| !s.is_empty())
+        .unwrap_or(memo)
+        .to_string()
+}
+
+pub fn parse_zettle_payee(p: &str) -> String {
+    p.split_once('*').map_or(p, |s| s.1).trim().replace('\'', "")
+}
+
 pub fn parse_makecommerce_memo(memo: &str) -> (String, Option<String>) {
     let mut s = memo.split(", ");
-    (
-        s.nth(2).map_or(String::from(""), String::from),
-        s.next().map(String::from),
-    )
+    match s.nth(2).filter(|p| !p.is_empty()) {
+        Some(payee) => (String::from(payee), s.next().map(String::from)),
+        None => (String::from("MakeCommerce"), Some(String::from(memo))),
+    }
 }
 
 pub fn parse_paypal_payee(p: &str) -> String {
@@ -78,18 +235,70 @@ pub fn parse_paypal_payee(p: &str) -> String {
     parts.join(" ")
 }
 
+/// Card purchases are imported with Revolut as the payee, while for transfers
+/// the recipient is used and top-ups are marked as such.
+pub fn parse_revolut_memo(memo: &str) -> (String, Option<String>) {
+    let lowercase_memo = memo.to_lowercase();
+    if memo.starts_with("Revolut**") {
+        (String::from("Revolut"), None)
+    } else if ["top-up", "top up", "topup"].iter().any(|t| lowercase_memo.contains(t)) {
+        (String::from("Revolut top-up"), Some(String::from(memo)))
+    } else if let Some(recipient) = memo.strip_prefix("Transfer to ").or_else(|| memo.strip_prefix("To ")) {
+        (String::from(recipient.trim()), Some(String::from(memo)))
+    } else {
+        (String::from("Revolut"), Some(String::from(memo)))
+    }
+}
+
+/// Returns true for payment reference codes: numbers, e.g. "1234", and Trustly references that
+/// consist of dash separated groups of 4 uppercase letters and digits, e.g. "T9A8-B7C6"
+fn is_reference_code(word: &str) -> bool {
+    let groups = word.split('-').collect::<Vec<&str>>();
+    let is_trustly_reference = groups.len() > 1
+        && word.chars().any(|c| c.is_ascii_digit())
+        && groups
+            .iter()
+            .all(|g| g.len() == 4 && g.chars().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()));
+    is_trustly_reference || !word.is_empty() && word.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Trustly memos consist of optional reference codes followed by the seller name
 pub fn parse_trustly_memo(memo: &str) -> (String, Option<String>) {
     let refund_memo = "Cross border transfer";
-    if refund_memo == memo {
-        (String::from("Trustly Group AB"), Some(String::from(refund_memo)))
+    let words = memo.split_whitespace().collect::<Vec<&str>>();
+    let reference_length = words.iter().take_while(|w| is_reference_code(w)).count();
+    if refund_memo == memo || reference_length == words.len() {
+        (String::from("Trustly Group AB"), Some(String::from(memo)))
+    } else if reference_length == 0 {
+        (String::from(memo), None)
     } else {
-        memo.split_once(' ')
-            .map_or((String::from("Trustly Group AB"), Some(String::from(memo))), |s| {
-                (String::from(s.1), Some(String::from(s.0)))
-            })
+        (
+            words[reference_length..].join(" "),
+            Some(words[..reference_length].join(" ")),
+        )
     }
 }
 
+// Memo prefixes of monthly card fees, in Latvian, Estonian and Lithuanian
+const CARD_FEE_MEMOS: &[&str] = &["Kartes mēneša maksa", "Kaardi kuutasu", "Kortelės mėnesio mokestis"];
+
+/// Payee of monthly card fees
+pub const CARD_FEE_PAYEE: &str = "Swedbank card fee";
+
+/// Monthly card fee memos contain the masked card number and the billing period, e.g.
+/// "Kartes mēneša maksa 000000******0000 02.2020". Returns just the card and the period
+/// for such memos, None for other memos.
+pub fn parse_card_fee_memo(memo: &str) -> Option<String> {
+    let details = CARD_FEE_MEMOS.iter().find_map(|p| memo.strip_prefix(p))?;
+    Some(
+        details
+            .split_whitespace()
+            .filter(|w| w.contains('*') || w.chars().all(|c| c.is_ascii_digit() || c == '.'))
+            .collect::<Vec<&str>>()
+            .join(" "),
+    )
+}
+
 pub fn parse_paysera_memo(memo: &str) -> (String, Option<String>) {
     memo.split_once(" pardevejs: ")
         .map_or((String::from("Paysera LT"), Some(String::from(memo))), |s| {
@@ -97,9 +306,87 @@ pub fn parse_paysera_memo(memo: &str) -> (String, Option<String>) {
         })
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
+    fn find_parser(payee: &str) -> Option<&(dyn MerchantParser + Sync)> {
+        MERCHANT_PARSERS.iter().find(|p| p.matches(payee)).map(|p| p.as_ref())
+    }
+
+    #[test]
+    fn test_no_merchant_parser() {
+        assert!(find_parser("Foobar").is_none());
+    }
+
+    #[test]
+    fn test_paypal_parser() {
+        assert_eq!(
+            find_parser("PAYPAL *foobar L2449 00000000000")
+                .map(|p| p.parse("PAYPAL *foobar L2449 00000000000", "Order 1")),
+            Some((String::from("foobar"), Some(String::from("Order 1"))))
+        );
+    }
+
+    #[test]
+    fn test_paypal_parser_repeated_memo() {
+        assert_eq!(
+            PayPal.parse("PAYPAL *foobar L2449 00000000000", "PAYPAL *foobar L2449 00000000000"),
+            (String::from("foobar"), None)
+        );
+    }
+
+    #[test]
+    fn test_makecommerce_parser() {
+        assert_eq!(
+            find_parser("MakeCommerce").map(|p| p.parse("MakeCommerce", "Maksekeskus/EE, st1, Payee, Memo, (1)")),
+            Some((String::from("Payee"), Some(String::from("Memo"))))
+        );
+    }
+
+    #[test]
+    fn test_memo_unless_payee() {
+        assert_eq!(memo_unless_payee("Shop", "Shop"), None);
+        assert_eq!(memo_unless_payee("Shop", "Shop 123"), None);
+        assert_eq!(memo_unless_payee("Shop", "Other"), Some(String::from("Other")));
+    }
+
+    #[test]
+    fn test_memo_unless_payee_whitespace() {
+        assert_eq!(memo_unless_payee("Shop", " Shop "), None);
+        assert_eq!(memo_unless_payee(" Shop ", "Shop"), None);
+        assert_eq!(memo_unless_payee("Shop ", "  Shop 123"), None);
+    }
+
+    #[test]
+    fn test_amazon_payees() {
+        assert_eq!(parse_amazon_payee("AMZN Mktp DE*AB12CD34E"), "Amazon Marketplace");
+        assert_eq!(parse_amazon_payee("Amazon Prime*AB12CD34E"), "Amazon Prime");
+        assert_eq!(parse_amazon_payee("AMZN Digital*AB12CD34E"), "Amazon Digital");
+        assert_eq!(parse_amazon_payee("AMZN*AB12CD34E"), "Amazon");
+    }
+
+    #[test]
+    fn test_amazon_digital_memo() {
+        assert_eq!(
+            parse_amazon_memo("AMZN Digital*1A2B3C4D5 Kindle Unlimited"),
+            String::from("1A2B3C4D5 Kindle Unlimited")
+        );
+    }
+
+    #[test]
+    fn test_amazon_marketplace_memo() {
+        assert_eq!(
+            parse_amazon_memo("AMZN Mktp DE*Foo Seller GmbH"),
+            String::from("Foo Seller GmbH")
+        );
+    }
+
+    #[test]
+    fn test_amazon_memo_without_details() {
+        assert_eq!(parse_amazon_memo("AMZN Mktp DE"), String::from("AMZN Mktp DE"));
+    }
+
     #[test]
     fn test_makecommerce_memo() {
         assert_eq!(
@@ -108,6 +395,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_short_makecommerce_memo() {
+        assert_eq!(
+            parse_makecommerce_memo("Maksekeskus/EE, st123"),
+            (
+                String::from("MakeCommerce"),
+                Some(String::from("Maksekeskus/EE, st123"))
+            )
+        );
+    }
+
     #[test]
     fn test_trustly_memo() {
         assert_eq!(
@@ -116,11 +414,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_trustly_multi_word_reference_memo() {
+        assert_eq!(
+            parse_trustly_memo("1234 T9A8-B7C6 Seller 24 Yo"),
+            (String::from("Seller 24 Yo"), Some(String::from("1234 T9A8-B7C6")))
+        );
+    }
+
+    #[test]
+    fn test_trustly_seller_with_digits() {
+        assert_eq!(
+            parse_trustly_memo("1234 7-ELEVEN"),
+            (String::from("7-ELEVEN"), Some(String::from("1234")))
+        );
+        assert_eq!(
+            parse_trustly_memo("1234 3M Store"),
+            (String::from("3M Store"), Some(String::from("1234")))
+        );
+        assert_eq!(parse_trustly_memo("3M Store"), (String::from("3M Store"), None));
+    }
+
+    #[test]
+    fn test_trustly_memo_without_reference() {
+        assert_eq!(parse_trustly_memo("Seller Yo"), (String::from("Seller Yo"), None));
+    }
+
+    #[test]
+    fn test_trustly_reference_only_memo() {
+        assert_eq!(
+            parse_trustly_memo("1234"),
+            (String::from("Trustly Group AB"), Some(String::from("1234")))
+        );
+    }
+
     #[test]
     fn test_trustly_refund_memo() {
         assert_eq!(
             parse_trustly_memo("Cross border transfer"),
-            (String::from("Trustly Group AB"), Some(String::from("Cross border transfer")))
+            (
+                String::from("Trustly Group AB"),
+                Some(String::from("Cross border transfer"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_revolut_purchase_memo() {
+        assert_eq!(
+            parse_revolut_memo("Revolut**1234* D02 R296 Dublin"),
+            (String::from("Revolut"), None)
+        );
+    }
+
+    #[test]
+    fn test_revolut_parser_payees() {
+        assert!(Revolut.matches("Revolut**1234*"));
+        assert!(Revolut.matches("Revolut Ltd"));
+        assert!(Revolut.matches("REVOLUT BANK UAB"));
+        assert!(!Revolut.matches("Revolution Bar"));
+    }
+
+    #[test]
+    fn test_revolut_top_up_memo() {
+        assert_eq!(
+            parse_revolut_memo("Top-Up by *1234"),
+            (String::from("Revolut top-up"), Some(String::from("Top-Up by *1234")))
+        );
+    }
+
+    #[test]
+    fn test_revolut_transfer_memo() {
+        assert_eq!(
+            parse_revolut_memo("Transfer to JANIS BERZINS"),
+            (
+                String::from("JANIS BERZINS"),
+                Some(String::from("Transfer to JANIS BERZINS"))
+            )
         );
     }
 
@@ -135,6 +505,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_card_fee_memo() {
+        assert_eq!(
+            parse_card_fee_memo("Kartes mēneša maksa 000000******0000 02.2020"),
+            Some(String::from("000000******0000 02.2020"))
+        );
+        assert_eq!(
+            parse_card_fee_memo("Kaardi kuutasu 000000******0000 02.2020"),
+            Some(String::from("000000******0000 02.2020"))
+        );
+        assert_eq!(parse_card_fee_memo("Kartes darījuma komisija"), None);
+    }
+
+    #[test]
+    fn test_zettle_payee() {
+        assert_eq!(parse_zettle_payee("Zettle_*Cafe"), String::from("Cafe"));
+    }
+
+    #[test]
+    fn test_zettle_parser_prefixes() {
+        assert!(Zettle.matches("IZ *Cafe"));
+        assert!(Zettle.matches("iZ *Cafe"));
+        assert!(Zettle.matches("Zettle_*Cafe"));
+        assert!(!Zettle.matches("Izakaya *Cafe"));
+    }
+
     #[test]
     fn test_paypal_payee() {
         assert_eq!(
@@ -1,20 +1,65 @@
 use std::error::Error;
+use std::thread;
+use std::time::Duration;
 
 extern crate serde;
 use serde::{Deserialize, Serialize};
 
-use log::{debug, error};
+use log::{debug, error, warn};
 
 static API_URL: &str = "https://api.youneedabudget.com";
 static APP_URL: &str = "https://app.youneedabudget.com";
+static MAX_RETRIES: u32 = 3;
+static USER_AGENT: &str = concat!("ynab-swedbank/", env!("CARGO_PKG_VERSION"));
 
 enum UrlType {
     AppUrl,
     ApiUrl,
 }
 
+/// Parses an amount string, ignoring a trailing currency code.
+/// Trailing minus sign is used for negative amounts, e.g. "12,99-"
 pub fn parse_i64_string(i: &str) -> Option<i64> {
-    i.replace(",", "").parse::<i64>().ok()
+    let amount = i.trim().trim_end_matches(|c: char| c.is_ascii_alphabetic()).trim_end();
+    let (amount, sign) = match amount.strip_suffix('-') {
+        Some(a) => (a, -1),
+        None => (amount, 1),
+    };
+    amount.replace(",", "").parse::<i64>().ok().map(|v| sign * v)
+}
+
+/// Returns true if the given string is shaped like a YNAB id, e.g. 01234567-89ab-cdef-0123-456789abcdef
+pub fn is_uuid(s: &str) -> bool {
+    let groups = s.split('-').collect::<Vec<&str>>();
+    groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+        && groups.iter().all(|g| g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Adds a hint about possibly wrong budget/account ids to "not found" errors
+fn with_id_hint(e: reqwest::Error) -> Box<dyn Error> {
+    if e.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+        format!(
+            "{}\nBudget or account not found, check that the budget and account ids are correct and not swapped",
+            e
+        )
+        .into()
+    } else {
+        e.into()
+    }
+}
+
+/// Server errors and exceeded rate limits are temporary, so the request can be retried
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Returns the delay from the Retry-After header, given in seconds
+fn retry_after(res: &reqwest::blocking::Response) -> Option<Duration> {
+    res.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
 }
 
 fn no_rollup() -> bool {
@@ -25,14 +70,28 @@ fn no_rollup() -> bool {
 pub struct YnabTransaction {
     pub import_id: String,
     pub date: String,
+    // Not sent when the payee id is set
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub payee_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee_id: Option<String>,
     pub memo: Option<String>,
     pub cleared: String,
     pub amount: i64,
     pub account_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_color: Option<String>,
 
     #[serde(skip, default = "no_rollup")]
     pub needs_rollup: bool,
+    // Payee as it appears in the CSV
+    #[serde(skip)]
+    pub raw_payee: String,
+    // False if the CSV payee did not match any known payee formats
+    #[serde(skip)]
+    pub payee_matched: bool,
 }
 
 impl YnabTransaction {
@@ -45,8 +104,11 @@ impl YnabTransaction {
 }
 
 pub struct YnabClient {
-    budget_id: String,
+    pub budget_id: String,
     pub account_id: String,
+    api_url: String,
+    // Delay before the first retry of a failed request, doubled for every following retry
+    retry_delay: Duration,
     client: reqwest::blocking::Client,
 }
 
@@ -75,6 +137,21 @@ struct GetAccountResponse {
     data: GetAccountResponseData,
 }
 
+#[derive(Deserialize)]
+struct YnabUser {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct GetUserResponseData {
+    user: YnabUser,
+}
+
+#[derive(Deserialize)]
+struct GetUserResponse {
+    data: GetUserResponseData,
+}
+
 #[derive(Deserialize)]
 struct GetBudgetResponseData {
     budget: YnabBudget,
@@ -85,9 +162,27 @@ struct GetBudgetResponse {
     data: GetBudgetResponseData,
 }
 
+/// Transaction that already exists in YNAB
+#[derive(Deserialize)]
+pub struct YnabTransactionDetail {
+    pub import_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GetTransactionsResponseData {
+    transactions: Vec<YnabTransactionDetail>,
+}
+
+#[derive(Deserialize)]
+struct GetTransactionsResponse {
+    data: GetTransactionsResponseData,
+}
+
 #[derive(Deserialize)]
 pub struct PostTransactionsResponseData {
-    // server_knowledge: i64,
+    pub server_knowledge: i64,
+    #[serde(default)]
+    pub transaction_ids: Vec<String>,
     pub duplicate_import_ids: Vec<String>,
     pub transactions: Vec<YnabTransaction>,
 }
@@ -103,69 +198,147 @@ struct PostTransactionsRequest<T> {
 }
 
 impl YnabClient {
-    pub fn new(budget_id: String, account_id: String, token: &str) -> Self {
+    pub fn new(budget_id: String, account_id: String, token: &str) -> Result<Self, Box<dyn Error>> {
+        // "last-used" and "default" can be used in place of the budget id
+        if !is_uuid(&budget_id) && budget_id != "last-used" && budget_id != "default" {
+            return Err(format!("Invalid budget id: {}", budget_id).into());
+        }
+        if !is_uuid(&account_id) {
+            return Err(format!("Invalid account id: {}", account_id).into());
+        }
+
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             reqwest::header::AUTHORIZATION,
             reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
         );
 
-        Self {
+        Ok(Self {
             budget_id,
             account_id,
+            api_url: String::from(API_URL),
+            retry_delay: Duration::from_secs(1),
             client: reqwest::blocking::Client::builder()
                 .default_headers(headers)
+                .user_agent(USER_AGENT)
                 .build()
                 .unwrap(),
+        })
+    }
+
+    /// Returns a client for another account in the same budget
+    pub fn with_account(&self, account_id: &str) -> Result<Self, Box<dyn Error>> {
+        if !is_uuid(account_id) {
+            return Err(format!("Invalid account id: {}", account_id).into());
         }
+        Ok(Self {
+            budget_id: self.budget_id.clone(),
+            account_id: String::from(account_id),
+            api_url: self.api_url.clone(),
+            retry_delay: self.retry_delay,
+            client: self.client.clone(),
+        })
+    }
+
+    /// Points the client to a different API server, used for testing against a mock server
+    #[cfg(test)]
+    pub fn with_api_url(self, api_url: &str) -> Self {
+        Self {
+            api_url: String::from(api_url),
+            ..self
+        }
+    }
+
+    /// Changes the delay between retries, used for testing without waiting
+    #[cfg(test)]
+    pub fn with_retry_delay(self, retry_delay: Duration) -> Self {
+        Self { retry_delay, ..self }
     }
 
     fn transactions_uri(&self) -> String {
-        format!("{}/v1/budgets/{}/transactions", API_URL, self.budget_id)
+        format!("{}/v1/budgets/{}/transactions", self.api_url, self.budget_id)
+    }
+
+    fn transaction_uri(&self, transaction_id: &str) -> String {
+        format!("{}/{}", self.transactions_uri(), transaction_id)
     }
 
     fn account_uri(&self, url_type: UrlType) -> String {
         match url_type {
-            UrlType::ApiUrl => format!("{}/v1/budgets/{}/accounts/{}", API_URL, self.budget_id, self.account_id),
+            UrlType::ApiUrl => format!(
+                "{}/v1/budgets/{}/accounts/{}",
+                self.api_url, self.budget_id, self.account_id
+            ),
             UrlType::AppUrl => format!("{}/{}/accounts/{}", APP_URL, self.budget_id, self.account_id),
         }
     }
 
+    fn account_transactions_uri(&self, since_date: &str) -> String {
+        format!(
+            "{}/transactions?since_date={}",
+            self.account_uri(UrlType::ApiUrl),
+            since_date
+        )
+    }
+
     pub fn app_account_uri(&self) -> String {
         self.account_uri(UrlType::AppUrl)
     }
 
+    fn user_uri(&self) -> String {
+        format!("{}/v1/user", self.api_url)
+    }
+
     fn budget_uri(&self) -> String {
-        format!("{}/v1/budgets/{}", API_URL, self.budget_id)
+        format!("{}/v1/budgets/{}", self.api_url, self.budget_id)
+    }
+
+    /// Sends a request, retrying it with an increasing delay if YNAB responds with a server error (5xx)
+    /// or the rate limit is exceeded (429), in which case the Retry-After header is used as the delay
+    /// when present. Other client errors (4xx) are returned right away, as sending the same request
+    /// again won't help.
+    fn send(
+        &self,
+        method: &str,
+        uri: &str,
+        request: impl Fn() -> reqwest::blocking::RequestBuilder,
+    ) -> Result<reqwest::blocking::Response, reqwest::Error> {
+        let mut retries = 0;
+        loop {
+            let (res, retry_after) = match request().send() {
+                Ok(r) => {
+                    debug!("{} {} -> {:?}", method, uri, r);
+                    let retry_after = retry_after(&r);
+                    (r.error_for_status(), retry_after)
+                }
+                Err(e) => (Err(e), None),
+            };
+            match res {
+                Err(e) if e.status().is_some_and(is_retryable) && retries < MAX_RETRIES => {
+                    let delay = retry_after.unwrap_or(self.retry_delay * 2u32.pow(retries));
+                    warn!("{} {} -> {}, retrying in {:?}", method, uri, e, delay);
+                    thread::sleep(delay);
+                    retries += 1;
+                }
+                Err(e) => {
+                    error!("{} {} -> {:?}", method, uri, e);
+                    return Err(e);
+                }
+                Ok(r) => return Ok(r),
+            }
+        }
     }
 
     fn get<T: for<'a> Deserialize<'a>>(&self, uri: &str) -> Result<T, reqwest::Error> {
-        self.client
-            .get(uri)
-            .send()
-            .and_then(|r| {
-                debug!("GET {} -> {:?}", uri, r);
-                r.json()
-            })
-            .map_err(|e| {
-                error!("GET {} -> {:?}", uri, e);
-                e
-            })
+        self.send("GET", uri, || self.client.get(uri))?.json()
     }
 
     fn post<S: Serialize, D: for<'a> Deserialize<'a>>(&self, body: S, uri: &str) -> Result<D, reqwest::Error> {
-        self.client
-            .post(uri)
-            .json(&body)
-            .send()
-            .and_then(|r| {
-                debug!("POST {} -> {:?}", uri, r);
-                r.json()
-            })
-            .map_err(|e| {
-                error!("POST {} -> {:?}", uri, e);
-                e
-            })
+        self.send("POST", uri, || self.client.post(uri).json(&body))?.json()
+    }
+
+    fn delete(&self, uri: &str) -> Result<(), reqwest::Error> {
+        self.send("DELETE", uri, || self.client.delete(uri)).map(|_| ())
     }
 
     pub fn post_transactions<T: Serialize>(&self, txns: T) -> Result<PostTransactionsResponseData, Box<dyn Error>> {
@@ -174,13 +347,172 @@ impl YnabClient {
         Ok(res.data)
     }
 
+    /// Deletes the transaction with the given YNAB transaction id
+    pub fn delete_transaction(&self, transaction_id: &str) -> Result<(), Box<dyn Error>> {
+        self.delete(&self.transaction_uri(transaction_id))?;
+        Ok(())
+    }
+
+    /// Returns account transactions on or after the given YYYY-MM-DD date
+    pub fn get_transactions(&self, since_date: &str) -> Result<Vec<YnabTransactionDetail>, Box<dyn Error>> {
+        let res: GetTransactionsResponse = self.get(&self.account_transactions_uri(since_date))?;
+        Ok(res.data.transactions)
+    }
+
+    /// Returns the id of the user that the access token belongs to. The request error is returned
+    /// as is, so that HTTP errors can be told apart from connection errors.
+    pub fn get_user_id(&self) -> Result<String, reqwest::Error> {
+        let res: GetUserResponse = self.get(&self.user_uri())?;
+        Ok(res.data.user.id)
+    }
+
     pub fn get_budget_currency(&self) -> Result<String, Box<dyn Error>> {
-        let res: GetBudgetResponse = self.get(&self.budget_uri())?;
+        let res: GetBudgetResponse = self.get(&self.budget_uri()).map_err(with_id_hint)?;
         Ok(res.data.budget.currency_format.iso_code)
     }
 
     pub fn get_acccount_balance(&self) -> Result<i64, Box<dyn Error>> {
-        let res: GetAccountResponse = self.get(&self.account_uri(UrlType::ApiUrl))?;
+        let res: GetAccountResponse = self.get(&self.account_uri(UrlType::ApiUrl)).map_err(with_id_hint)?;
         Ok(res.data.account.balance)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+
+    static BUDGET_ID: &str = "01234567-89ab-cdef-0123-456789abcdef";
+    static ACCOUNT_ID: &str = "fedcba98-7654-3210-fedc-ba9876543210";
+
+    #[test]
+    fn test_post_transactions_response() {
+        let res: PostTransactionsResponse = serde_json::from_str(
+            r#"{"data": {"server_knowledge": 42, "duplicate_import_ids": ["1"], "transactions": []}}"#,
+        )
+        .unwrap();
+        assert_eq!(res.data.server_knowledge, 42);
+        assert_eq!(res.data.duplicate_import_ids, vec![String::from("1")]);
+    }
+
+    #[test]
+    fn test_delete_transaction() {
+        let server = MockServer::with_responses(vec![(200, "{}")]);
+        let client = YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token")
+            .unwrap()
+            .with_api_url(&server.url);
+
+        assert!(client.delete_transaction("abc").is_ok());
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "DELETE");
+        assert_eq!(requests[0].path, format!("/v1/budgets/{}/transactions/abc", BUDGET_ID));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let server = MockServer::with_responses(vec![(200, "{}")]);
+        let client = YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token")
+            .unwrap()
+            .with_api_url(&server.url);
+
+        client.delete_transaction("abc").unwrap();
+        assert_eq!(
+            server.requests()[0].header("user-agent"),
+            Some(format!("ynab-swedbank/{}", env!("CARGO_PKG_VERSION")).as_str())
+        );
+    }
+
+    fn test_client(server: &MockServer) -> YnabClient {
+        YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token")
+            .unwrap()
+            .with_api_url(&server.url)
+            .with_retry_delay(Duration::ZERO)
+    }
+
+    #[test]
+    fn test_retry_server_error() {
+        let server = MockServer::with_responses(vec![
+            (502, "Bad Gateway"),
+            (200, r#"{"data": {"account": {"balance": 12990}}}"#),
+        ]);
+        assert_eq!(test_client(&server).get_acccount_balance().unwrap(), 12990);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_server_error_limit() {
+        let server = MockServer::start(|_| (500, String::from("Internal Server Error")));
+        assert!(test_client(&server).get_acccount_balance().is_err());
+        assert_eq!(server.requests().len(), 1 + MAX_RETRIES as usize);
+    }
+
+    #[test]
+    fn test_retry_rate_limit() {
+        let server = MockServer::with_responses(vec![
+            (
+                429,
+                r#"{"error": {"id": "429", "name": "too_many_requests", "detail": "Too many requests"}}"#,
+            ),
+            (200, r#"{"data": {"account": {"balance": 12990}}}"#),
+        ]);
+        server.add_header("Retry-After", "0");
+        // Retry-After takes precedence over the default delay, which would make the test time out
+        let client = test_client(&server).with_retry_delay(Duration::from_secs(600));
+        assert_eq!(client.get_acccount_balance().unwrap(), 12990);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_no_retry_client_error() {
+        let server = MockServer::with_responses(vec![
+            (
+                400,
+                r#"{"error": {"id": "400", "name": "bad_request", "detail": "Bad request"}}"#,
+            ),
+            (
+                201,
+                r#"{"data": {"server_knowledge": 1, "duplicate_import_ids": [], "transactions": []}}"#,
+            ),
+        ]);
+        let txns: Vec<YnabTransaction> = vec![];
+        assert!(test_client(&server).post_transactions(txns).is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_i64_string() {
+        assert_eq!(parse_i64_string("12,99"), Some(1299));
+    }
+
+    #[test]
+    fn test_parse_i64_string_trailing_minus() {
+        assert_eq!(parse_i64_string("12,99-"), Some(-1299));
+    }
+
+    #[test]
+    fn test_parse_i64_string_currency() {
+        assert_eq!(parse_i64_string("12,99 EUR"), Some(1299));
+        assert_eq!(parse_i64_string("12,99- EUR"), Some(-1299));
+    }
+
+    #[test]
+    fn test_is_uuid() {
+        assert!(is_uuid(BUDGET_ID));
+        assert!(!is_uuid("01234567-89ab-cdef-0123"));
+        assert!(!is_uuid("0123456789abcdef0123456789abcdef"));
+        assert!(!is_uuid("0123456x-89ab-cdef-0123-456789abcdef"));
+    }
+
+    #[test]
+    fn test_new_client() {
+        assert!(YnabClient::new(String::from(BUDGET_ID), String::from(ACCOUNT_ID), "token").is_ok());
+        assert!(YnabClient::new(String::from("last-used"), String::from(ACCOUNT_ID), "token").is_ok());
+    }
+
+    #[test]
+    fn test_new_client_invalid_ids() {
+        assert!(YnabClient::new(String::from("budget"), String::from(ACCOUNT_ID), "token").is_err());
+        assert!(YnabClient::new(String::from(BUDGET_ID), String::from("last-used"), "token").is_err());
+    }
+}